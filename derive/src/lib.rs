@@ -0,0 +1,145 @@
+//! `#[derive(Role)]`, re-exported as `auth_middleware::Role`.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Expr, ExprLit, Fields, Lit};
+
+/// Ids of the predefined `auth_middleware::auth::Role`s, custom roles cannot reuse them.
+const RESERVED_IDS: [u8; 3] = [100, 150, 255];
+
+/// Implements `auth_middleware::auth::AuthRole` for a fieldless `#[repr(u8)]` enum, so
+/// `require_role` accepts its variants. The discriminants are the role ids and the names
+/// are the variant names in snake case, e.g. `ReportViewer` is `report_viewer` and
+/// `HTTPAdmin` is `http_admin`. The ids of the predefined roles are refused.
+#[proc_macro_derive(Role)]
+pub fn derive_role(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let Data::Enum(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            input,
+            "Role can only be derived for enums",
+        ));
+    };
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "Role cannot be derived for generic enums",
+        ));
+    }
+    if !has_repr_u8(input)? {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "Role needs #[repr(u8)], the discriminants are the role ids",
+        ));
+    }
+
+    let ident = &input.ident;
+    let mut arms = Vec::new();
+    let mut variants = Vec::new();
+    // Known while the discriminants are literals, the const assertion below covers the rest
+    let mut next_id = Some(0);
+    for variant in &data.variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(syn::Error::new_spanned(
+                variant,
+                "Role variants cannot have fields",
+            ));
+        }
+        let id = match &variant.discriminant {
+            Some((_, discriminant)) => literal_id(discriminant)?,
+            None => next_id,
+        };
+        if let Some(id) = id.filter(|id| RESERVED_IDS.iter().any(|r| u16::from(*r) == *id)) {
+            return Err(syn::Error::new_spanned(
+                variant,
+                format!("role id {id} belongs to a predefined role"),
+            ));
+        }
+        next_id = id.map(|id| id + 1);
+        let variant_ident = &variant.ident;
+        let name = snake_case(&variant_ident.to_string());
+        arms.push(quote! { #ident::#variant_ident => #name });
+        variants.push(quote! { #ident::#variant_ident });
+    }
+
+    let reserved = RESERVED_IDS.map(proc_macro2::Literal::u8_unsuffixed);
+    let reserved = quote! { #(#reserved)|* };
+    let (last, rest) = RESERVED_IDS.split_last().expect("some ids are reserved");
+    let rest: Vec<String> = rest.iter().map(u8::to_string).collect();
+    let reserved_message = format!(
+        "role ids {} and {last} belong to the predefined roles",
+        rest.join(", ")
+    );
+
+    Ok(quote! {
+        impl ::core::convert::From<#ident> for u8 {
+            fn from(role: #ident) -> u8 {
+                role as u8
+            }
+        }
+
+        const _: () = {
+            #(
+                assert!(!matches!(#variants as u8, #reserved), #reserved_message);
+            )*
+        };
+
+        impl ::auth_middleware::auth::AuthRole for #ident {
+            const VARIANTS: &'static [Self] = &[#(#variants),*];
+
+            fn name(self) -> &'static str {
+                match self {
+                    #(#arms,)*
+                }
+            }
+        }
+    })
+}
+
+/// The value of an integer literal discriminant, `None` for other expressions.
+fn literal_id(discriminant: &Expr) -> syn::Result<Option<u16>> {
+    match discriminant {
+        Expr::Lit(ExprLit {
+            lit: Lit::Int(int), ..
+        }) => int.base10_parse().map(Some),
+        _ => Ok(None),
+    }
+}
+
+fn has_repr_u8(input: &DeriveInput) -> syn::Result<bool> {
+    let mut repr_u8 = false;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("repr"))
+    {
+        attr.parse_nested_meta(|meta| {
+            repr_u8 |= meta.path.is_ident("u8");
+            Ok(())
+        })?;
+    }
+    Ok(repr_u8)
+}
+
+/// A run of capitals is one word, up to the capital starting the next one.
+fn snake_case(ident: &str) -> String {
+    let chars: Vec<char> = ident.chars().collect();
+    let mut name = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let after_word = !chars[i - 1].is_uppercase();
+            let starts_word = chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+            if after_word || starts_word {
+                name.push('_');
+            }
+        }
+        name.extend(c.to_lowercase());
+    }
+    name
+}
@@ -0,0 +1,4 @@
+CREATE TABLE users (
+    id TEXT PRIMARY KEY NOT NULL,
+    pw_hash TEXT NOT NULL
+);
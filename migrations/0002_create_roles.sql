@@ -0,0 +1,13 @@
+-- Role ids are the permission levels of `auth::Role`
+CREATE TABLE roles (
+    id INTEGER PRIMARY KEY NOT NULL CHECK (id BETWEEN 0 AND 255),
+    name TEXT NOT NULL UNIQUE
+);
+
+INSERT INTO roles (id, name) VALUES (100, 'user'), (255, 'admin');
+
+CREATE TABLE user_roles (
+    user_id TEXT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    role_id INTEGER NOT NULL REFERENCES roles (id) ON DELETE CASCADE,
+    PRIMARY KEY (user_id, role_id)
+);
@@ -0,0 +1,4 @@
+-- User ids used to be the username, keep them as is for existing accounts
+ALTER TABLE users ADD COLUMN username TEXT NOT NULL DEFAULT '';
+UPDATE users SET username = id;
+CREATE UNIQUE INDEX users_username ON users (username);
@@ -0,0 +1,8 @@
+CREATE TABLE sessions (
+    id TEXT PRIMARY KEY NOT NULL,
+    data TEXT NOT NULL,
+    -- Unix timestamp, in seconds
+    expiry_date INTEGER NOT NULL
+);
+
+CREATE INDEX sessions_expiry_date ON sessions (expiry_date);
@@ -0,0 +1,8 @@
+-- Single-use tokens, e.g. for password resets. Only a hash of the token is stored
+CREATE TABLE tokens (
+    hash TEXT PRIMARY KEY NOT NULL,
+    kind TEXT NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    -- Unix timestamp, in seconds
+    expires_at INTEGER NOT NULL
+);
@@ -0,0 +1,3 @@
+ALTER TABLE users ADD COLUMN verified BOOLEAN NOT NULL DEFAULT FALSE;
+-- Accounts created before verification existed are trusted as is
+UPDATE users SET verified = TRUE;
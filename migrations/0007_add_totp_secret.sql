@@ -0,0 +1,2 @@
+-- Base32 TOTP secret, NULL while two-factor authentication is off
+ALTER TABLE users ADD COLUMN totp_secret TEXT;
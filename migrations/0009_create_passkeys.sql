@@ -0,0 +1,11 @@
+-- WebAuthn credentials, a user can register several
+CREATE TABLE passkeys (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    -- The serialized webauthn-rs `Passkey`
+    passkey TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+);
+
+CREATE INDEX passkeys_user_id ON passkeys (user_id);
@@ -0,0 +1,13 @@
+-- Keys for machine clients. Only a hash of the key is stored
+CREATE TABLE api_keys (
+    id TEXT PRIMARY KEY NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    hash TEXT NOT NULL UNIQUE,
+    -- Unix timestamps, in seconds
+    created_at INTEGER NOT NULL,
+    last_used_at INTEGER,
+    revoked_at INTEGER
+);
+
+CREATE INDEX api_keys_user_id ON api_keys (user_id);
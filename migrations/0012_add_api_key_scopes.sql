@@ -0,0 +1,3 @@
+-- JSON array of the role ids a key is limited to, NULL for keys that can do
+-- everything their owner can
+ALTER TABLE api_keys ADD COLUMN scopes TEXT;
@@ -0,0 +1,2 @@
+-- Between user and admin, see `auth::RoleGraph`
+INSERT INTO roles (id, name) VALUES (150, 'editor');
@@ -0,0 +1,7 @@
+-- Replay and guessing protection of the second factor. The last TOTP step a user
+-- logged in with, codes of earlier steps are refused
+ALTER TABLE users ADD COLUMN totp_last_step INTEGER;
+-- Failed second factor attempts in a row
+ALTER TABLE users ADD COLUMN second_factor_failures INTEGER NOT NULL DEFAULT 0;
+-- Unix timestamp, in seconds, until which the second factor is locked
+ALTER TABLE users ADD COLUMN second_factor_locked_until INTEGER;
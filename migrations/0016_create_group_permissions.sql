@@ -0,0 +1,6 @@
+-- Named permissions members of a group get on top of those of the group's roles
+CREATE TABLE group_permissions (
+    group_id TEXT NOT NULL REFERENCES groups (id) ON DELETE CASCADE,
+    permission TEXT NOT NULL,
+    PRIMARY KEY (group_id, permission)
+);
@@ -0,0 +1,7 @@
+-- Scopes are now a JSON array of permission names instead of role ids. Which
+-- permissions a role grants is configured, not stored, so the keys scoped to roles
+-- cannot be converted and are revoked instead.
+UPDATE api_keys
+SET scopes = '[]',
+    revoked_at = COALESCE(revoked_at, CAST(strftime('%s', 'now') AS INTEGER))
+WHERE scopes IS NOT NULL;
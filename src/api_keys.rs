@@ -0,0 +1,236 @@
+use crate::server_error_message;
+use leptos::*;
+use leptos_router::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyInfo {
+    pub id: String,
+    pub name: String,
+    /// Dates, e.g. `2024-05-01`
+    pub created: String,
+    pub last_used: Option<String>,
+    /// Permissions the key is limited to, `None` for full access
+    pub scopes: Option<Vec<String>>,
+    pub revoked: bool,
+}
+
+#[component]
+pub fn ApiKeysPage() -> impl IntoView {
+    view! {
+        <ApiKeys/>
+        <a href="/protected">"Back"</a>
+    }
+}
+
+#[island]
+fn ApiKeys() -> impl IntoView {
+    let create_action = create_server_action::<CreateApiKey>();
+    let revoke_action = create_server_action::<RevokeApiKey>();
+    let scopes = create_local_resource(|| (), |_| list_api_key_scopes());
+    let api_keys = create_local_resource(
+        move || (create_action.version().get(), revoke_action.version().get()),
+        |_| list_api_keys(),
+    );
+    let error = move || {
+        create_action
+            .value()
+            .get()
+            .and_then(|res| res.err())
+            .or_else(|| revoke_action.value().get().and_then(|res| res.err()))
+            .map(|err| server_error_message(&err))
+    };
+    let new_key = move || create_action.value().get().and_then(|res| res.ok());
+
+    view! {
+        <Suspense fallback=|| view! { <div uk-spinner></div> }>
+            {move || api_keys.get().map(|api_keys| match api_keys {
+                Ok(api_keys) if api_keys.is_empty() => view! {
+                    <p>"You have no API keys yet."</p>
+                }.into_view(),
+                Ok(api_keys) => view! {
+                    <ul class="uk-list">
+                        {api_keys.into_iter().map(|api_key| view! {
+                            <li>
+                                {api_key.name} " (created " {api_key.created} ", "
+                                {api_key.last_used.map_or("never used".to_string(), |date| format!("last used {date}"))}
+                                ", "
+                                {api_key.scopes.map_or("full access".to_string(), |scopes| format!("limited to {}", scopes.join(", ")))}
+                                ")"
+                                {if api_key.revoked {
+                                    view! { " revoked" }.into_view()
+                                } else {
+                                    view! {
+                                        <ActionForm action=revoke_action>
+                                            <input type="hidden" name="id" value=api_key.id/>
+                                            <button class="uk-button uk-button-small" type="submit">
+                                                "Revoke"
+                                            </button>
+                                        </ActionForm>
+                                    }.into_view()
+                                }}
+                            </li>
+                        }).collect_view()}
+                    </ul>
+                }.into_view(),
+                Err(err) => server_error_message(&err).into_view(),
+            })}
+        </Suspense>
+        <ActionForm action=create_action>
+            <div class="uk-margin">
+                <input class="uk-input uk-form-width-medium" type="text" name="name"
+                    placeholder="Key name, e.g. Backup script" maxlength="64" required/>
+            </div>
+            <div class="uk-margin">
+                <p>"Limit the key to, or leave all unticked for everything your account can do:"</p>
+                <Suspense fallback=|| view! { <div uk-spinner></div> }>
+                    {move || scopes.get().map(|scopes| match scopes {
+                        Ok(scopes) => scopes.into_iter().enumerate().map(|(i, scope)| view! {
+                            <label class="uk-margin-small-right">
+                                // Indexed, `serde_qs` reads the ticked ones into a list
+                                <input class="uk-checkbox" type="checkbox"
+                                    name=format!("scopes[{i}]") value=scope.clone()/>
+                                " " {scope}
+                            </label>
+                        }).collect_view(),
+                        Err(err) => server_error_message(&err).into_view(),
+                    })}
+                </Suspense>
+            </div>
+            <button class="button-auth" type="submit">"Create an API key"</button>
+        </ActionForm>
+        <Show when=move || error().is_some()>
+            <p class="uk-text-danger">{error}</p>
+        </Show>
+        {move || new_key().map(|key| view! {
+            <p>
+                "Send it as " <code>"Authorization: Bearer <key>"</code>
+                ". Copy it now, it won't be shown again:"
+            </p>
+            <p><code>{key}</code></p>
+        })}
+    }
+}
+
+#[server(ListApiKeys)]
+#[middleware(crate::compose_from_fn!(crate::require_session_login))]
+async fn list_api_keys() -> Result<Vec<ApiKeyInfo>, ServerFnError> {
+    use crate::auth::{AuthSession, UserStore};
+    use axum::Extension;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let Some(user) = auth_session.user else {
+        res.set_status(http::StatusCode::UNAUTHORIZED);
+        return Err(ServerFnError::new(""));
+    };
+
+    let api_keys = auth_session
+        .backend
+        .store()
+        .get_api_keys(&user.id.0)
+        .await?;
+    Ok(api_keys
+        .into_iter()
+        .map(|api_key| ApiKeyInfo {
+            revoked: api_key.is_revoked(),
+            id: api_key.id,
+            name: api_key.name,
+            created: api_key.created_at.date().to_string(),
+            last_used: api_key.last_used_at.map(|at| at.date().to_string()),
+            scopes: api_key.scopes.map(|scopes| {
+                let mut scopes: Vec<_> = scopes.into_iter().collect();
+                scopes.sort();
+                scopes
+            }),
+        })
+        .collect())
+}
+
+/// The permissions the keys of the user can be limited to, those the user has.
+#[server(ListApiKeyScopes)]
+#[middleware(crate::compose_from_fn!(crate::require_session_login))]
+async fn list_api_key_scopes() -> Result<Vec<String>, ServerFnError> {
+    use crate::auth::AuthSession;
+    use axum::Extension;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let Some(user) = auth_session.user else {
+        res.set_status(http::StatusCode::UNAUTHORIZED);
+        return Err(ServerFnError::new(""));
+    };
+
+    Ok(auth_session.backend.named_permissions(&user).await?)
+}
+
+#[server(CreateApiKey)]
+#[middleware(crate::compose_from_fn!(crate::require_session_login))]
+async fn create_api_key(
+    name: String,
+    #[server(default)] scopes: Vec<String>,
+) -> Result<String, ServerFnError> {
+    use crate::auth::{AuthSession, Error};
+    use axum::Extension;
+    use std::collections::HashSet;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let Some(user) = auth_session.user else {
+        res.set_status(http::StatusCode::UNAUTHORIZED);
+        return Err(ServerFnError::new(""));
+    };
+
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > 64 {
+        res.set_status(http::StatusCode::BAD_REQUEST);
+        return Err(ServerFnError::new(
+            "The name must be 1 to 64 characters long",
+        ));
+    }
+
+    // No scopes makes a plain key, any a token limited to them
+    let scopes = (!scopes.is_empty()).then(|| scopes.into_iter().collect::<HashSet<_>>());
+
+    match auth_session
+        .backend
+        .create_api_key(&user, name, scopes)
+        .await
+    {
+        Ok((key, _)) => Ok(key),
+        Err(err @ Error::ScopeNotGranted) => {
+            res.set_status(http::StatusCode::FORBIDDEN);
+            Err(ServerFnError::new(err))
+        }
+        Err(_) => {
+            res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(ServerFnError::ServerError("".to_string()))
+        }
+    }
+}
+
+#[server(RevokeApiKey)]
+#[middleware(crate::compose_from_fn!(crate::require_session_login))]
+async fn revoke_api_key(id: String) -> Result<(), ServerFnError> {
+    use crate::auth::{AuthSession, UserStore};
+    use axum::Extension;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let Some(user) = auth_session.user else {
+        res.set_status(http::StatusCode::UNAUTHORIZED);
+        return Err(ServerFnError::new(""));
+    };
+
+    if !auth_session
+        .backend
+        .store()
+        .revoke_api_key(&user.id.0, &id, time::OffsetDateTime::now_utc())
+        .await?
+    {
+        res.set_status(http::StatusCode::NOT_FOUND);
+        return Err(ServerFnError::new("No such API key"));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use super::tokens;
+use std::collections::HashSet;
+use time::OffsetDateTime;
+
+/// Marks keys in logs and secret scanners, the rest is a random token.
+const KEY_PREFIX: &str = "lak_";
+
+/// A long-lived key for machine clients. Only the hash of the key is stored.
+#[derive(Debug, Clone)]
+pub struct ApiKey {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub hash: String,
+    /// Names of the permissions the key is limited to, e.g. `data:read`, `None` for a
+    /// key that can do everything its owner can. Requests with it get those of these
+    /// permissions its owner still has, see [`super::Backend::scoped_permissions`].
+    pub scopes: Option<HashSet<String>>,
+    pub created_at: OffsetDateTime,
+    pub last_used_at: Option<OffsetDateTime>,
+    pub revoked_at: Option<OffsetDateTime>,
+}
+
+impl ApiKey {
+    pub fn is_revoked(&self) -> bool {
+        self.revoked_at.is_some()
+    }
+}
+
+/// Returns a new key along with the hash to store.
+pub fn generate_key() -> (String, String) {
+    let key = format!("{KEY_PREFIX}{}", tokens::generate_token().0);
+    let hash = hash_key(&key);
+    (key, hash)
+}
+
+/// Tells API keys apart from other bearer credentials, e.g. JWTs.
+pub fn is_api_key(token: &str) -> bool {
+    token.starts_with(KEY_PREFIX)
+}
+
+/// Keys are random like tokens, so the same unsalted hash is enough.
+pub fn hash_key(key: &str) -> String {
+    tokens::hash_token(key)
+}
@@ -0,0 +1,12 @@
+/// A named set of users. Members get the roles and permissions of the group on top
+/// of their own, see `Backend::get_group_permissions`.
+#[derive(Debug, Clone)]
+pub struct Group {
+    pub id: String,
+    pub name: String,
+    pub roles: Vec<u8>,
+    /// Named permissions, e.g. `reports:read`, granted without a role that bundles them
+    pub permissions: Vec<String>,
+    /// Ids of the member users
+    pub members: Vec<String>,
+}
@@ -0,0 +1,465 @@
+//! Users from an Apache-style `.htpasswd` file with bcrypt or Argon2 hashes, and their
+//! roles from a sidecar file. Both are reread when they change.
+//!
+//! Everything else, e.g. tokens, passkeys and TOTP secrets, lives in an inner store. The
+//! file's users are mirrored into it so those records have a user to belong to, but only
+//! the file decides who exists, what their password is and which roles they have.
+//!
+//! So nothing creates users either: logging in through OIDC or a trusted proxy as
+//! someone the file lacks fails with [`Error::UsersReadOnly`] instead of provisioning
+//! them. Add them to the file first, external identities can then be linked to them.
+
+use super::{
+    validate_username, ApiKey, Error, ExternalIdentity, Group, LoginMethod, Role, RoleRegistry,
+    StoredPasskey, Token, TokenKind, User, UserId, UserStore,
+};
+use crate::config::HtpasswdConfig;
+use axum::async_trait;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use time::OffsetDateTime;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    pw_hash: String,
+    roles: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct Loaded {
+    entries: HashMap<String, Entry>,
+    /// Modification times of the htpasswd and roles files when they were read
+    modified: (Option<SystemTime>, Option<SystemTime>),
+}
+
+/// A [`UserStore`] whose users come from an htpasswd file. Changing users through the
+/// store fails with [`Error::UsersReadOnly`], edit the file instead.
+pub struct HtpasswdStore {
+    inner: Arc<dyn UserStore>,
+    htpasswd_file: PathBuf,
+    roles_file: Option<PathBuf>,
+    /// Looks up the names in the roles file
+    roles: RoleRegistry,
+    loaded: RwLock<Loaded>,
+    /// Serializes reloads, so two of them don't mirror the same user twice
+    reloading: tokio::sync::Mutex<()>,
+}
+
+impl std::fmt::Debug for HtpasswdStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HtpasswdStore")
+            .field("htpasswd_file", &self.htpasswd_file)
+            .field("roles_file", &self.roles_file)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HtpasswdStore {
+    /// Reads the files and mirrors their users into `inner`. Role names in the roles
+    /// file are looked up in `roles`.
+    pub async fn open(
+        config: &HtpasswdConfig,
+        inner: Arc<dyn UserStore>,
+        roles: RoleRegistry,
+    ) -> Result<Self, Error> {
+        let store = Self {
+            inner,
+            htpasswd_file: config.file.clone().into(),
+            roles_file: config.roles_file.clone().map(Into::into),
+            roles,
+            loaded: RwLock::new(Loaded::default()),
+            reloading: tokio::sync::Mutex::new(()),
+        };
+        store.reload_if_changed().await?;
+        Ok(store)
+    }
+
+    /// Rereads the files if either changed since they were last read.
+    pub async fn reload_if_changed(&self) -> Result<(), Error> {
+        let _reloading = self.reloading.lock().await;
+        let modified = (
+            modified(Some(&self.htpasswd_file)).await?,
+            modified(self.roles_file.as_ref()).await?,
+        );
+        if self.loaded.read().unwrap().modified == modified {
+            return Ok(());
+        }
+
+        let htpasswd = tokio::fs::read_to_string(&self.htpasswd_file).await?;
+        let roles = match &self.roles_file {
+            Some(roles_file) => {
+                parse_roles(&tokio::fs::read_to_string(roles_file).await?, &self.roles)
+            }
+            None => HashMap::new(),
+        };
+        let entries: HashMap<String, Entry> = parse_htpasswd(&htpasswd)
+            .into_iter()
+            .map(|(username, pw_hash)| {
+                let roles = roles
+                    .get(&username)
+                    .cloned()
+                    .unwrap_or_else(|| vec![Role::User.into()]);
+                (username, Entry { pw_hash, roles })
+            })
+            .collect();
+
+        for (username, entry) in &entries {
+            self.mirror(username, entry).await?;
+        }
+        *self.loaded.write().unwrap() = Loaded { entries, modified };
+        Ok(())
+    }
+
+    /// Spawns a task checking the files for changes every `period`.
+    pub fn spawn_reload(self: &Arc<Self>, period: Duration) {
+        let store = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                if let Err(err) = store.reload_if_changed().await {
+                    eprintln!("Failed to reload {}: {err}", store.htpasswd_file.display());
+                }
+            }
+        });
+    }
+
+    async fn mirror(&self, username: &str, entry: &Entry) -> Result<(), Error> {
+        match self.inner.get_user_by_username(username).await? {
+            Some(user) => {
+                if user.pw_hash != entry.pw_hash {
+                    self.inner
+                        .update_password(&user.id.0, &entry.pw_hash)
+                        .await?;
+                }
+                if user.roles != entry.roles {
+                    self.inner.update_roles(&user.id.0, &entry.roles).await?;
+                }
+                Ok(())
+            }
+            None => {
+                self.inner
+                    .insert_user(&User {
+                        id: UserId(uuid::Uuid::new_v4().to_string()),
+                        username: username.into(),
+                        pw_hash: entry.pw_hash.clone(),
+                        roles: entry.roles.clone(),
+                        // Whoever added the line vouches for the account
+                        verified: true,
+                        totp_secret: None,
+                        identities: Vec::new(),
+                    })
+                    .await
+            }
+        }
+    }
+
+    /// Applies the file to a user of the inner store, `None` if the file lacks them.
+    fn overlay_file(&self, user: Option<User>) -> Option<User> {
+        let mut user = user?;
+        let loaded = self.loaded.read().unwrap();
+        let entry = loaded.entries.get(&user.username)?;
+        user.pw_hash = entry.pw_hash.clone();
+        user.roles = entry.roles.clone();
+        Some(user)
+    }
+}
+
+async fn modified(path: Option<&PathBuf>) -> Result<Option<SystemTime>, Error> {
+    match path {
+        Some(path) => Ok(Some(tokio::fs::metadata(path).await?.modified()?)),
+        None => Ok(None),
+    }
+}
+
+/// `username:hash` lines. Only bcrypt and Argon2 hashes are accepted, the older
+/// htpasswd schemes are too weak and their users are skipped. So are usernames
+/// registration would refuse, see [`validate_username`].
+fn parse_htpasswd(contents: &str) -> Vec<(String, String)> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let Some((username, pw_hash)) = line.split_once(':') else {
+                eprintln!("Skipping an htpasswd line without a ':'");
+                return None;
+            };
+            if let Err(err) = validate_username(username) {
+                eprintln!("Skipping {username:?} in htpasswd: {err}");
+                return None;
+            }
+            if !["$2a$", "$2b$", "$2y$", "$argon2"]
+                .iter()
+                .any(|prefix| pw_hash.starts_with(prefix))
+            {
+                eprintln!("Skipping {username} in htpasswd, only bcrypt and Argon2 are supported");
+                return None;
+            }
+            Some((username.into(), pw_hash.into()))
+        })
+        .collect()
+}
+
+/// `username: role, role` lines with the role names known to `registry`.
+fn parse_roles(contents: &str, registry: &RoleRegistry) -> HashMap<String, Vec<u8>> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (username, roles) = line.split_once(':')?;
+            let roles = roles
+                .split(',')
+                .map(str::trim)
+                .filter_map(|name| {
+                    let role = registry.by_name(name);
+                    if role.is_none() {
+                        eprintln!("Skipping unknown role {name} of {username}");
+                    }
+                    role
+                })
+                .collect();
+            Some((username.trim().into(), roles))
+        })
+        .collect()
+}
+
+#[async_trait]
+impl UserStore for HtpasswdStore {
+    async fn get_user(&self, id: &str) -> Result<Option<User>, Error> {
+        Ok(self.overlay_file(self.inner.get_user(id).await?))
+    }
+
+    async fn get_user_by_username(&self, username: &str) -> Result<Option<User>, Error> {
+        Ok(self.overlay_file(self.inner.get_user_by_username(username).await?))
+    }
+
+    async fn get_user_by_identity(
+        &self,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Option<User>, Error> {
+        Ok(self.overlay_file(self.inner.get_user_by_identity(provider, subject).await?))
+    }
+
+    async fn insert_user(&self, _user: &User) -> Result<(), Error> {
+        Err(Error::UsersReadOnly)
+    }
+
+    async fn update_password(&self, _id: &str, _pw_hash: &str) -> Result<(), Error> {
+        Err(Error::UsersReadOnly)
+    }
+
+    async fn set_verified(&self, id: &str, verified: bool) -> Result<(), Error> {
+        self.inner.set_verified(id, verified).await
+    }
+
+    async fn set_totp_secret(&self, id: &str, secret: Option<&str>) -> Result<(), Error> {
+        self.inner.set_totp_secret(id, secret).await
+    }
+
+    async fn use_totp_step(&self, id: &str, step: i64) -> Result<bool, Error> {
+        self.inner.use_totp_step(id, step).await
+    }
+
+    async fn add_second_factor_failure(&self, id: &str) -> Result<u32, Error> {
+        self.inner.add_second_factor_failure(id).await
+    }
+
+    async fn reset_second_factor_failures(
+        &self,
+        id: &str,
+        locked_until: Option<OffsetDateTime>,
+    ) -> Result<(), Error> {
+        self.inner
+            .reset_second_factor_failures(id, locked_until)
+            .await
+    }
+
+    async fn get_second_factor_lock(&self, id: &str) -> Result<Option<OffsetDateTime>, Error> {
+        self.inner.get_second_factor_lock(id).await
+    }
+
+    async fn update_roles(&self, _id: &str, _roles: &[u8]) -> Result<(), Error> {
+        Err(Error::UsersReadOnly)
+    }
+
+    async fn delete_user(&self, _id: &str) -> Result<(), Error> {
+        Err(Error::UsersReadOnly)
+    }
+
+    async fn replace_recovery_codes(&self, user_id: &str, hashes: &[String]) -> Result<(), Error> {
+        self.inner.replace_recovery_codes(user_id, hashes).await
+    }
+
+    async fn get_recovery_codes(&self, user_id: &str) -> Result<Vec<String>, Error> {
+        self.inner.get_recovery_codes(user_id).await
+    }
+
+    async fn take_recovery_code(&self, user_id: &str, hash: &str) -> Result<bool, Error> {
+        self.inner.take_recovery_code(user_id, hash).await
+    }
+
+    async fn insert_identity(&self, identity: &ExternalIdentity) -> Result<(), Error> {
+        self.inner.insert_identity(identity).await
+    }
+
+    async fn insert_passkey(&self, passkey: &StoredPasskey) -> Result<(), Error> {
+        self.inner.insert_passkey(passkey).await
+    }
+
+    async fn get_passkeys(&self, user_id: &str) -> Result<Vec<StoredPasskey>, Error> {
+        self.inner.get_passkeys(user_id).await
+    }
+
+    async fn update_passkey(&self, passkey: &StoredPasskey) -> Result<(), Error> {
+        self.inner.update_passkey(passkey).await
+    }
+
+    async fn delete_login_method(
+        &self,
+        user_id: &str,
+        method: &LoginMethod,
+    ) -> Result<bool, Error> {
+        // The password is the line in the file
+        if *method == LoginMethod::Password {
+            return Err(Error::UsersReadOnly);
+        }
+        self.inner.delete_login_method(user_id, method).await
+    }
+
+    async fn insert_api_key(&self, api_key: &ApiKey) -> Result<(), Error> {
+        self.inner.insert_api_key(api_key).await
+    }
+
+    async fn get_api_keys(&self, user_id: &str) -> Result<Vec<ApiKey>, Error> {
+        self.inner.get_api_keys(user_id).await
+    }
+
+    async fn get_api_key_by_hash(&self, hash: &str) -> Result<Option<ApiKey>, Error> {
+        self.inner.get_api_key_by_hash(hash).await
+    }
+
+    async fn touch_api_key(&self, id: &str, used_at: OffsetDateTime) -> Result<(), Error> {
+        self.inner.touch_api_key(id, used_at).await
+    }
+
+    async fn revoke_api_key(
+        &self,
+        user_id: &str,
+        id: &str,
+        revoked_at: OffsetDateTime,
+    ) -> Result<bool, Error> {
+        self.inner.revoke_api_key(user_id, id, revoked_at).await
+    }
+
+    async fn insert_token(&self, token: &Token) -> Result<(), Error> {
+        self.inner.insert_token(token).await
+    }
+
+    async fn get_token(&self, kind: TokenKind, hash: &str) -> Result<Option<Token>, Error> {
+        self.inner.get_token(kind, hash).await
+    }
+
+    async fn take_token(&self, kind: TokenKind, hash: &str) -> Result<Option<Token>, Error> {
+        self.inner.take_token(kind, hash).await
+    }
+
+    async fn delete_tokens(&self, user_id: &str, kind: TokenKind) -> Result<(), Error> {
+        self.inner.delete_tokens(user_id, kind).await
+    }
+
+    async fn insert_group(&self, group: &Group) -> Result<(), Error> {
+        self.inner.insert_group(group).await
+    }
+
+    async fn get_groups(&self) -> Result<Vec<Group>, Error> {
+        self.inner.get_groups().await
+    }
+
+    async fn get_user_groups(&self, user_id: &str) -> Result<Vec<Group>, Error> {
+        self.inner.get_user_groups(user_id).await
+    }
+
+    async fn update_group_roles(&self, id: &str, roles: &[u8]) -> Result<(), Error> {
+        self.inner.update_group_roles(id, roles).await
+    }
+
+    async fn update_group_permissions(
+        &self,
+        id: &str,
+        permissions: &[String],
+    ) -> Result<(), Error> {
+        self.inner.update_group_permissions(id, permissions).await
+    }
+
+    async fn delete_group(&self, id: &str) -> Result<bool, Error> {
+        self.inner.delete_group(id).await
+    }
+
+    async fn add_group_member(&self, group_id: &str, user_id: &str) -> Result<bool, Error> {
+        self.inner.add_group_member(group_id, user_id).await
+    }
+
+    async fn remove_group_member(&self, group_id: &str, user_id: &str) -> Result<bool, Error> {
+        self.inner.remove_group_member(group_id, user_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bcrypt_and_argon2_users() {
+        let contents = "
+            # comment
+            alice:$2y$05$c4WoMPo3SXsafkva.HHa6uXQZWr7oboPiC2bT/r7q1BB8I2s0BRqC
+
+            bob:$argon2id$v=19$m=19456,t=2,p=1$c2FsdA$aGFzaA
+            carol:$apr1$salt$hash
+            dave:{SHA}W6ph5Mm5Pz8GgiULbPgzG37mj9g=
+            no separator
+            a:$2y$05$short
+            bad name:$2y$05$c4WoMPo3SXsafkva.HHa6uXQZWr7oboPiC2bT/r7q1BB8I2s0BRqC
+        ";
+        assert_eq!(
+            parse_htpasswd(contents),
+            vec![
+                (
+                    "alice".to_string(),
+                    "$2y$05$c4WoMPo3SXsafkva.HHa6uXQZWr7oboPiC2bT/r7q1BB8I2s0BRqC".to_string()
+                ),
+                (
+                    "bob".to_string(),
+                    "$argon2id$v=19$m=19456,t=2,p=1$c2FsdA$aGFzaA".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn hash_keeps_colons() {
+        let users = parse_htpasswd("alice:$argon2id$v=19$m=19456,t=2,p=1$a:b$c");
+        assert_eq!(users[0].1, "$argon2id$v=19$m=19456,t=2,p=1$a:b$c");
+    }
+
+    #[test]
+    fn parses_roles_by_name() {
+        let contents = "
+            # comment
+            alice: admin, editor
+            bob:user,owner
+            carol:
+            no separator
+        ";
+        let roles = parse_roles(contents, &RoleRegistry::default());
+        assert_eq!(roles["alice"], vec![255, 150]);
+        assert_eq!(roles["bob"], vec![100]);
+        assert_eq!(roles["carol"], Vec::<u8>::new());
+        assert_eq!(roles.len(), 3);
+    }
+}
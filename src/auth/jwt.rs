@@ -0,0 +1,177 @@
+//! Stateless sessions for API clients. Access tokens are short-lived JWTs carrying what
+//! the middlewares need, so they are checked without a store lookup. The refresh tokens
+//! that renew them are single-use [`TokenKind::Refresh`] tokens, kept in the store so
+//! they can be revoked.
+//!
+//! [`TokenKind::Refresh`]: super::TokenKind::Refresh
+
+use super::{Error, User, UserId};
+use crate::config::{JwtAlgorithm, JwtConfig, JwtKeyConfig};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use time::{Duration, OffsetDateTime};
+
+/// Claims of an access token.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AccessClaims {
+    iss: String,
+    sub: String,
+    iat: i64,
+    exp: i64,
+    username: String,
+    roles: Vec<u8>,
+    verified: bool,
+}
+
+/// An access token along with the refresh token to renew it.
+#[derive(Debug, Clone)]
+pub struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub expires_in: Duration,
+}
+
+/// The keys from the config, indexed by `kid`.
+pub struct JwtKeys {
+    issuer: String,
+    signing_kid: String,
+    signing_algorithm: Algorithm,
+    encoding_key: EncodingKey,
+    decoding_keys: HashMap<String, (Algorithm, DecodingKey)>,
+    access_ttl: Duration,
+    refresh_ttl: Duration,
+}
+
+impl std::fmt::Debug for JwtKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JwtKeys")
+            .field("issuer", &self.issuer)
+            .field("signing_kid", &self.signing_kid)
+            .finish_non_exhaustive()
+    }
+}
+
+impl JwtKeys {
+    /// Reads the keys, the first one signs. `issuer` goes into the `iss` claim.
+    pub fn from_config(config: &JwtConfig, issuer: &str) -> Result<Self, Error> {
+        let Some(signing) = config.keys.first() else {
+            return Err(Error::InvalidConfig("jwt.keys is empty"));
+        };
+
+        let mut decoding_keys = HashMap::new();
+        for key in &config.keys {
+            let decoding_key = match key.algorithm {
+                JwtAlgorithm::Hs256 => DecodingKey::from_secret(secret(key)?),
+                JwtAlgorithm::EdDsa => DecodingKey::from_ed_pem(&read_pem(
+                    key.public_key_file.as_deref(),
+                    "jwt.keys.public_key_file is required for EdDSA",
+                )?)?,
+            };
+            if decoding_keys
+                .insert(key.kid.clone(), (algorithm(key.algorithm), decoding_key))
+                .is_some()
+            {
+                return Err(Error::InvalidConfig("jwt.keys has a duplicate kid"));
+            }
+        }
+
+        let encoding_key = match signing.algorithm {
+            JwtAlgorithm::Hs256 => EncodingKey::from_secret(secret(signing)?),
+            JwtAlgorithm::EdDsa => EncodingKey::from_ed_pem(&read_pem(
+                signing.private_key_file.as_deref(),
+                "jwt.keys.private_key_file is required for the signing key",
+            )?)?,
+        };
+
+        Ok(Self {
+            issuer: issuer.into(),
+            signing_kid: signing.kid.clone(),
+            signing_algorithm: algorithm(signing.algorithm),
+            encoding_key,
+            decoding_keys,
+            access_ttl: Duration::seconds(config.access_ttl_secs as i64),
+            refresh_ttl: Duration::seconds(config.refresh_ttl_secs as i64),
+        })
+    }
+
+    pub fn access_ttl(&self) -> Duration {
+        self.access_ttl
+    }
+
+    pub fn refresh_ttl(&self) -> Duration {
+        self.refresh_ttl
+    }
+
+    /// Signs an access token for `user` with the current key.
+    pub fn encode(&self, user: &User) -> Result<String, Error> {
+        let now = OffsetDateTime::now_utc();
+        let claims = AccessClaims {
+            iss: self.issuer.clone(),
+            sub: user.id.0.clone(),
+            iat: now.unix_timestamp(),
+            exp: (now + self.access_ttl).unix_timestamp(),
+            username: user.username.clone(),
+            roles: user.roles.clone(),
+            verified: user.verified,
+        };
+        let mut header = Header::new(self.signing_algorithm);
+        header.kid = Some(self.signing_kid.clone());
+        Ok(jsonwebtoken::encode(&header, &claims, &self.encoding_key)?)
+    }
+
+    /// Checks an access token and returns the user it was issued to, as of issuance.
+    /// Only the fields in the claims are set, the returned user has no password hash,
+    /// TOTP secret or identities.
+    pub fn decode(&self, token: &str) -> Result<User, Error> {
+        let header = jsonwebtoken::decode_header(token).map_err(|_| Error::InvalidToken)?;
+        // The algorithm comes from the key, not from the token, to rule out key confusion
+        let (algorithm, key) = header
+            .kid
+            .and_then(|kid| self.decoding_keys.get(&kid))
+            .ok_or(Error::InvalidToken)?;
+
+        let mut validation = Validation::new(*algorithm);
+        validation.leeway = 0;
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_required_spec_claims(&["exp", "iss", "sub"]);
+        let claims = jsonwebtoken::decode::<AccessClaims>(token, key, &validation)
+            .map_err(|_| Error::InvalidToken)?
+            .claims;
+
+        Ok(User {
+            id: UserId(claims.sub),
+            username: claims.username,
+            pw_hash: String::new(),
+            roles: claims.roles,
+            verified: claims.verified,
+            totp_secret: None,
+            identities: Vec::new(),
+        })
+    }
+}
+
+fn algorithm(algorithm: JwtAlgorithm) -> Algorithm {
+    match algorithm {
+        JwtAlgorithm::Hs256 => Algorithm::HS256,
+        JwtAlgorithm::EdDsa => Algorithm::EdDSA,
+    }
+}
+
+fn secret(key: &JwtKeyConfig) -> Result<&[u8], Error> {
+    match key.secret.as_deref() {
+        // RFC 7518 asks for a key at least as long as the hash
+        Some(secret) if secret.len() >= 32 => Ok(secret.as_bytes()),
+        Some(_) => Err(Error::InvalidConfig(
+            "jwt.keys.secret must be at least 32 bytes",
+        )),
+        None => Err(Error::InvalidConfig(
+            "jwt.keys.secret is required for HS256",
+        )),
+    }
+}
+
+fn read_pem(path: Option<&str>, missing: &'static str) -> Result<Vec<u8>, Error> {
+    let path = path.ok_or(Error::InvalidConfig(missing))?;
+    Ok(std::fs::read(path)?)
+}
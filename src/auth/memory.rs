@@ -0,0 +1,437 @@
+use super::{
+    ApiKey, Error, ExternalIdentity, Group, LoginMethod, StoredPasskey, Token, TokenKind, User,
+    UserStore,
+};
+use axum::async_trait;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use time::OffsetDateTime;
+
+/// Keeps users in a process local map, everything is lost on restart.
+#[derive(Default, Debug)]
+pub struct MemoryStore {
+    users: RwLock<HashMap<String, User>>,
+    tokens: RwLock<HashMap<String, Token>>,
+    recovery_codes: RwLock<HashMap<String, Vec<String>>>,
+    passkeys: RwLock<HashMap<String, StoredPasskey>>,
+    api_keys: RwLock<HashMap<String, ApiKey>>,
+    groups: RwLock<HashMap<String, Group>>,
+    /// By user id
+    totp_steps: RwLock<HashMap<String, i64>>,
+    /// Failures in a row and the lock, by user id
+    second_factor_failures: RwLock<HashMap<String, (u32, Option<OffsetDateTime>)>>,
+}
+
+#[async_trait]
+impl UserStore for MemoryStore {
+    async fn get_user(&self, id: &str) -> Result<Option<User>, Error> {
+        Ok(self.users.read().unwrap().get(id).cloned())
+    }
+
+    async fn get_user_by_username(&self, username: &str) -> Result<Option<User>, Error> {
+        Ok(self
+            .users
+            .read()
+            .unwrap()
+            .values()
+            .find(|user| user.username == username)
+            .cloned())
+    }
+
+    async fn get_user_by_identity(
+        &self,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Option<User>, Error> {
+        Ok(self
+            .users
+            .read()
+            .unwrap()
+            .values()
+            .find(|user| {
+                user.identities
+                    .iter()
+                    .any(|identity| identity.provider == provider && identity.subject == subject)
+            })
+            .cloned())
+    }
+
+    async fn insert_user(&self, user: &User) -> Result<(), Error> {
+        let mut users = self.users.write().unwrap();
+        if users.values().any(|u| u.username == user.username) {
+            return Err(Error::UsernameTaken);
+        }
+        users.insert(user.id.0.clone(), user.clone());
+        Ok(())
+    }
+
+    async fn update_password(&self, id: &str, pw_hash: &str) -> Result<(), Error> {
+        if let Some(user) = self.users.write().unwrap().get_mut(id) {
+            user.pw_hash = pw_hash.into();
+        }
+        Ok(())
+    }
+
+    async fn set_verified(&self, id: &str, verified: bool) -> Result<(), Error> {
+        if let Some(user) = self.users.write().unwrap().get_mut(id) {
+            user.verified = verified;
+        }
+        Ok(())
+    }
+
+    async fn set_totp_secret(&self, id: &str, secret: Option<&str>) -> Result<(), Error> {
+        if let Some(user) = self.users.write().unwrap().get_mut(id) {
+            user.totp_secret = secret.map(Into::into);
+        }
+        self.totp_steps.write().unwrap().remove(id);
+        Ok(())
+    }
+
+    async fn use_totp_step(&self, id: &str, step: i64) -> Result<bool, Error> {
+        if !self.users.read().unwrap().contains_key(id) {
+            return Ok(false);
+        }
+        let mut totp_steps = self.totp_steps.write().unwrap();
+        if totp_steps.get(id).is_some_and(|last| *last >= step) {
+            return Ok(false);
+        }
+        totp_steps.insert(id.into(), step);
+        Ok(true)
+    }
+
+    async fn add_second_factor_failure(&self, id: &str) -> Result<u32, Error> {
+        let mut failures = self.second_factor_failures.write().unwrap();
+        let (count, _) = failures.entry(id.into()).or_default();
+        *count += 1;
+        Ok(*count)
+    }
+
+    async fn reset_second_factor_failures(
+        &self,
+        id: &str,
+        locked_until: Option<OffsetDateTime>,
+    ) -> Result<(), Error> {
+        self.second_factor_failures
+            .write()
+            .unwrap()
+            .insert(id.into(), (0, locked_until));
+        Ok(())
+    }
+
+    async fn get_second_factor_lock(&self, id: &str) -> Result<Option<OffsetDateTime>, Error> {
+        Ok(self
+            .second_factor_failures
+            .read()
+            .unwrap()
+            .get(id)
+            .and_then(|(_, locked_until)| *locked_until))
+    }
+
+    async fn update_roles(&self, id: &str, roles: &[u8]) -> Result<(), Error> {
+        if let Some(user) = self.users.write().unwrap().get_mut(id) {
+            user.roles = roles.to_vec();
+        }
+        Ok(())
+    }
+
+    async fn delete_user(&self, id: &str) -> Result<(), Error> {
+        self.users.write().unwrap().remove(id);
+        self.recovery_codes.write().unwrap().remove(id);
+        self.totp_steps.write().unwrap().remove(id);
+        self.second_factor_failures.write().unwrap().remove(id);
+        self.passkeys
+            .write()
+            .unwrap()
+            .retain(|_, passkey| passkey.user_id != id);
+        self.api_keys
+            .write()
+            .unwrap()
+            .retain(|_, api_key| api_key.user_id != id);
+        self.tokens
+            .write()
+            .unwrap()
+            .retain(|_, token| token.user_id != id);
+        for group in self.groups.write().unwrap().values_mut() {
+            group.members.retain(|member| member != id);
+        }
+        Ok(())
+    }
+
+    async fn replace_recovery_codes(&self, user_id: &str, hashes: &[String]) -> Result<(), Error> {
+        self.recovery_codes
+            .write()
+            .unwrap()
+            .insert(user_id.into(), hashes.to_vec());
+        Ok(())
+    }
+
+    async fn get_recovery_codes(&self, user_id: &str) -> Result<Vec<String>, Error> {
+        Ok(self
+            .recovery_codes
+            .read()
+            .unwrap()
+            .get(user_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn take_recovery_code(&self, user_id: &str, hash: &str) -> Result<bool, Error> {
+        let mut recovery_codes = self.recovery_codes.write().unwrap();
+        let Some(hashes) = recovery_codes.get_mut(user_id) else {
+            return Ok(false);
+        };
+        let len = hashes.len();
+        hashes.retain(|h| h != hash);
+        Ok(hashes.len() != len)
+    }
+
+    async fn insert_identity(&self, identity: &ExternalIdentity) -> Result<(), Error> {
+        let mut users = self.users.write().unwrap();
+        let linked = users
+            .values()
+            .flat_map(|user| &user.identities)
+            .any(|linked| {
+                linked.provider == identity.provider && linked.subject == identity.subject
+            });
+        if linked {
+            return Err(Error::IdentityInUse);
+        }
+        if let Some(user) = users.get_mut(&identity.user_id) {
+            user.identities.push(identity.clone());
+        }
+        Ok(())
+    }
+
+    async fn insert_passkey(&self, passkey: &StoredPasskey) -> Result<(), Error> {
+        self.passkeys
+            .write()
+            .unwrap()
+            .insert(passkey.id.clone(), passkey.clone());
+        Ok(())
+    }
+
+    async fn get_passkeys(&self, user_id: &str) -> Result<Vec<StoredPasskey>, Error> {
+        Ok(self
+            .passkeys
+            .read()
+            .unwrap()
+            .values()
+            .filter(|passkey| passkey.user_id == user_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn update_passkey(&self, passkey: &StoredPasskey) -> Result<(), Error> {
+        if let Some(stored) = self.passkeys.write().unwrap().get_mut(&passkey.id) {
+            stored.passkey = passkey.passkey.clone();
+        }
+        Ok(())
+    }
+
+    async fn delete_login_method(
+        &self,
+        user_id: &str,
+        method: &LoginMethod,
+    ) -> Result<bool, Error> {
+        // Both locks at once, so no other removal runs in between
+        let mut users = self.users.write().unwrap();
+        let mut passkeys = self.passkeys.write().unwrap();
+        let Some(user) = users.get_mut(user_id) else {
+            return Ok(false);
+        };
+
+        let exists = match method {
+            LoginMethod::Password => user.has_password(),
+            LoginMethod::External { provider, subject } => user
+                .identities
+                .iter()
+                .any(|identity| identity.provider == *provider && identity.subject == *subject),
+            LoginMethod::Passkey { id } => passkeys
+                .get(id)
+                .is_some_and(|passkey| passkey.user_id == user_id),
+        };
+        if !exists {
+            return Ok(false);
+        }
+        let count = usize::from(user.has_password())
+            + user.identities.len()
+            + passkeys
+                .values()
+                .filter(|passkey| passkey.user_id == user_id)
+                .count();
+        if count == 1 {
+            return Err(Error::LastLoginMethod);
+        }
+
+        match method {
+            LoginMethod::Password => user.pw_hash.clear(),
+            LoginMethod::External { provider, subject } => user
+                .identities
+                .retain(|identity| identity.provider != *provider || identity.subject != *subject),
+            LoginMethod::Passkey { id } => {
+                passkeys.remove(id);
+            }
+        }
+        Ok(true)
+    }
+
+    async fn insert_api_key(&self, api_key: &ApiKey) -> Result<(), Error> {
+        self.api_keys
+            .write()
+            .unwrap()
+            .insert(api_key.id.clone(), api_key.clone());
+        Ok(())
+    }
+
+    async fn get_api_keys(&self, user_id: &str) -> Result<Vec<ApiKey>, Error> {
+        let mut api_keys: Vec<_> = self
+            .api_keys
+            .read()
+            .unwrap()
+            .values()
+            .filter(|api_key| api_key.user_id == user_id)
+            .cloned()
+            .collect();
+        api_keys.sort_by_key(|api_key| api_key.created_at);
+        Ok(api_keys)
+    }
+
+    async fn get_api_key_by_hash(&self, hash: &str) -> Result<Option<ApiKey>, Error> {
+        Ok(self
+            .api_keys
+            .read()
+            .unwrap()
+            .values()
+            .find(|api_key| api_key.hash == hash)
+            .cloned())
+    }
+
+    async fn touch_api_key(&self, id: &str, used_at: OffsetDateTime) -> Result<(), Error> {
+        if let Some(api_key) = self.api_keys.write().unwrap().get_mut(id) {
+            api_key.last_used_at = Some(used_at);
+        }
+        Ok(())
+    }
+
+    async fn revoke_api_key(
+        &self,
+        user_id: &str,
+        id: &str,
+        revoked_at: OffsetDateTime,
+    ) -> Result<bool, Error> {
+        let mut api_keys = self.api_keys.write().unwrap();
+        match api_keys.get_mut(id) {
+            Some(api_key) if api_key.user_id == user_id && !api_key.is_revoked() => {
+                api_key.revoked_at = Some(revoked_at);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn insert_token(&self, token: &Token) -> Result<(), Error> {
+        let mut tokens = self.tokens.write().unwrap();
+        tokens.retain(|_, token| !token.is_expired());
+        tokens.insert(token.hash.clone(), token.clone());
+        Ok(())
+    }
+
+    async fn get_token(&self, kind: TokenKind, hash: &str) -> Result<Option<Token>, Error> {
+        Ok(self
+            .tokens
+            .read()
+            .unwrap()
+            .get(hash)
+            .filter(|token| token.kind == kind)
+            .cloned())
+    }
+
+    async fn take_token(&self, kind: TokenKind, hash: &str) -> Result<Option<Token>, Error> {
+        let mut tokens = self.tokens.write().unwrap();
+        if tokens.get(hash).is_some_and(|token| token.kind == kind) {
+            Ok(tokens.remove(hash))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn delete_tokens(&self, user_id: &str, kind: TokenKind) -> Result<(), Error> {
+        self.tokens
+            .write()
+            .unwrap()
+            .retain(|_, token| token.user_id != user_id || token.kind != kind);
+        Ok(())
+    }
+
+    async fn insert_group(&self, group: &Group) -> Result<(), Error> {
+        let mut groups = self.groups.write().unwrap();
+        if groups.values().any(|g| g.name == group.name) {
+            return Err(Error::GroupNameTaken);
+        }
+        groups.insert(
+            group.id.clone(),
+            Group {
+                members: Vec::new(),
+                ..group.clone()
+            },
+        );
+        Ok(())
+    }
+
+    async fn get_groups(&self) -> Result<Vec<Group>, Error> {
+        let mut groups: Vec<_> = self.groups.read().unwrap().values().cloned().collect();
+        groups.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(groups)
+    }
+
+    async fn get_user_groups(&self, user_id: &str) -> Result<Vec<Group>, Error> {
+        Ok(self
+            .get_groups()
+            .await?
+            .into_iter()
+            .filter(|group| group.members.iter().any(|member| member == user_id))
+            .collect())
+    }
+
+    async fn update_group_roles(&self, id: &str, roles: &[u8]) -> Result<(), Error> {
+        if let Some(group) = self.groups.write().unwrap().get_mut(id) {
+            group.roles = roles.to_vec();
+        }
+        Ok(())
+    }
+
+    async fn update_group_permissions(
+        &self,
+        id: &str,
+        permissions: &[String],
+    ) -> Result<(), Error> {
+        if let Some(group) = self.groups.write().unwrap().get_mut(id) {
+            group.permissions = permissions.to_vec();
+        }
+        Ok(())
+    }
+
+    async fn delete_group(&self, id: &str) -> Result<bool, Error> {
+        Ok(self.groups.write().unwrap().remove(id).is_some())
+    }
+
+    async fn add_group_member(&self, group_id: &str, user_id: &str) -> Result<bool, Error> {
+        let mut groups = self.groups.write().unwrap();
+        let group = groups.get_mut(group_id).ok_or(Error::UnknownGroup)?;
+        if group.members.iter().any(|member| member == user_id) {
+            return Ok(false);
+        }
+        group.members.push(user_id.into());
+        Ok(true)
+    }
+
+    async fn remove_group_member(&self, group_id: &str, user_id: &str) -> Result<bool, Error> {
+        let mut groups = self.groups.write().unwrap();
+        let Some(group) = groups.get_mut(group_id) else {
+            return Ok(false);
+        };
+        let len = group.members.len();
+        group.members.retain(|member| member != user_id);
+        Ok(group.members.len() != len)
+    }
+}
@@ -0,0 +1,1152 @@
+use argon2::password_hash::{
+    self, rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+};
+use argon2::Argon2;
+use axum::async_trait;
+use axum_login::{AuthUser, AuthnBackend, AuthzBackend};
+use std::collections::HashSet;
+use std::sync::{Arc, OnceLock};
+
+pub mod api_keys;
+mod groups;
+pub mod htpasswd;
+pub mod jwt;
+#[cfg(feature = "memory-store")]
+pub mod memory;
+pub mod oidc;
+pub mod passkey;
+pub mod permissions;
+pub mod proxy;
+pub mod recovery;
+pub mod roles;
+pub mod sqlite;
+mod store;
+mod tokens;
+pub mod totp;
+
+pub use api_keys::ApiKey;
+pub use groups::Group;
+pub use htpasswd::HtpasswdStore;
+#[cfg(feature = "memory-store")]
+pub use memory::MemoryStore;
+pub use oidc::ExternalIdentity;
+pub use passkey::StoredPasskey;
+pub use permissions::{Permission, RolePermissions};
+pub use roles::{AuthRole, RoleGraph, RoleRegistry};
+pub use sqlite::SqliteStore;
+pub use store::{DynStore, UserStore};
+pub use tokens::{hash_token, Token, TokenKind};
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Role {
+    User = 100,
+    Editor = 150,
+    Admin = 255,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::User, Role::Editor, Role::Admin];
+
+    /// The name in the `roles` table
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl From<Role> for u8 {
+    fn from(r: Role) -> u8 {
+        r as u8
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UserId(pub String);
+
+#[derive(Clone)]
+pub enum Credentials {
+    Password {
+        username: String,
+        password: String,
+    },
+    /// The answer to a challenge from [`Backend::start_passkey_authentication`],
+    /// `state` is what was kept server side in the meantime.
+    Passkey {
+        user_id: String,
+        credential: Box<passkey::PublicKeyCredential>,
+        state: Box<passkey::PasskeyAuthentication>,
+    },
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Credentials::Password { username, .. } => f
+                .debug_struct("Password")
+                .field("username", username)
+                .field("password", &"[redacted]")
+                .finish(),
+            Credentials::Passkey { user_id, .. } => f
+                .debug_struct("Passkey")
+                .field("user_id", user_id)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("username is already taken")]
+    UsernameTaken,
+    #[error("invalid username: {0}")]
+    InvalidUsername(&'static str),
+    #[error("weak password: {0}")]
+    WeakPassword(&'static str),
+    #[error("invalid or expired token")]
+    InvalidToken,
+    #[error("this account is already linked to another user")]
+    IdentityInUse,
+    #[error("the last way to log in cannot be removed")]
+    LastLoginMethod,
+    #[error("too many invalid codes, try again later")]
+    SecondFactorLocked,
+    #[error("no such login method")]
+    UnknownLoginMethod,
+    #[error("group name is already taken")]
+    GroupNameTaken,
+    #[error("invalid group name: {0}")]
+    InvalidGroupName(&'static str),
+    #[error("no such group")]
+    UnknownGroup,
+    #[error("a key cannot have permissions its owner lacks")]
+    ScopeNotGranted,
+    #[error("you can only grant roles and permissions you have")]
+    RoleNotGranted,
+    #[error("passkeys are not enabled")]
+    PasskeysDisabled,
+    #[error("JWT sessions are not enabled")]
+    JwtDisabled,
+    #[error("users are managed in the htpasswd file")]
+    UsersReadOnly,
+    #[error("invalid configuration: {0}")]
+    InvalidConfig(&'static str),
+    #[error("roles inherit from each other: {0}")]
+    RoleCycle(String),
+    #[error("OpenID Connect login failed: {0}")]
+    Oidc(&'static str),
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error(transparent)]
+    Jwt(#[from] jsonwebtoken::errors::Error),
+    #[error(transparent)]
+    Webauthn(#[from] webauthn_rs::prelude::WebauthnError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    PasswordHash(#[from] password_hash::Error),
+    #[error(transparent)]
+    Bcrypt(#[from] bcrypt::BcryptError),
+    #[error(transparent)]
+    TaskJoin(#[from] tokio::task::JoinError),
+    #[error(transparent)]
+    Sqlx(#[from] sqlx::Error),
+    #[error(transparent)]
+    Migrate(#[from] sqlx::migrate::MigrateError),
+    /// Errors raised by [`UserStore`] implementations outside this crate.
+    #[error(transparent)]
+    Store(Box<dyn std::error::Error + Send + Sync>),
+}
+
+#[derive(Clone)]
+pub struct User {
+    pub id: UserId,
+    pub username: String,
+    /// Empty for accounts created through an external provider
+    pub pw_hash: String,
+    pub roles: Vec<u8>,
+    /// Set once the user followed the link sent by [`Backend::issue_token`]
+    /// with [`TokenKind::AccountVerification`]
+    pub verified: bool,
+    /// Base32 TOTP secret, set once the user enrolled a second factor
+    pub totp_secret: Option<String>,
+    /// Accounts at external providers the user can log in with
+    pub identities: Vec<ExternalIdentity>,
+}
+
+impl std::fmt::Debug for User {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("User")
+            .field("id", &self.id)
+            .field("username", &self.username)
+            .field("pw_hash", &"[redacted]")
+            .field("roles", &self.roles)
+            .field("verified", &self.verified)
+            .field("totp_enabled", &self.totp_secret.is_some())
+            .field("identities", &self.identities)
+            .finish()
+    }
+}
+
+impl User {
+    pub fn has_password(&self) -> bool {
+        !self.pw_hash.is_empty()
+    }
+}
+
+impl AuthUser for User {
+    type Id = String;
+
+    fn id(&self) -> Self::Id {
+        self.id.0.clone()
+    }
+
+    // Derived from the password hash, so changing the password invalidates
+    // every session of the user
+    fn session_auth_hash(&self) -> &[u8] {
+        self.pw_hash.as_bytes()
+    }
+}
+
+/// Request extension of a request authenticated without the session, by its
+/// `Authorization` header or its TLS client certificate. See `require_api_key`,
+/// `require_jwt`, `require_basic_auth` and `require_client_cert`.
+/// Generic over the user and permission types, so middlewares of other backends can
+/// insert it too.
+#[derive(Debug, Clone)]
+pub struct BearerAuth<U = User, P = Permission> {
+    pub user: U,
+    /// Limits the request to these permissions, see [`Backend::scoped_permissions`]
+    pub scopes: Option<HashSet<P>>,
+}
+
+/// A way for a user to log in, see [`Backend::login_methods`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoginMethod {
+    Password,
+    External { provider: String, subject: String },
+    Passkey { id: String },
+}
+
+/// The backend used by the app, the store is picked at startup.
+pub type AppBackend = Backend<DynStore>;
+
+pub type AuthSession = axum_login::AuthSession<AppBackend>;
+
+/// Session key holding a [`PendingSecondFactor`] between the password check
+/// and the second factor. The user is not logged in until the latter succeeds.
+pub const PENDING_SECOND_FACTOR_KEY: &str = "auth.pending_second_factor";
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct PendingSecondFactor {
+    pub user_id: String,
+}
+
+/// Failed second factor attempts in a row before it is locked, see
+/// [`Backend::verify_second_factor`].
+pub const MAX_SECOND_FACTOR_FAILURES: u32 = 5;
+/// How long the second factor stays locked after too many failures.
+pub const SECOND_FACTOR_LOCKOUT: time::Duration = time::Duration::minutes(15);
+
+/// Authentication and permission logic on top of a [`UserStore`].
+pub struct Backend<S: ?Sized> {
+    store: Arc<S>,
+    webauthn: Option<Arc<webauthn_rs::Webauthn>>,
+    jwt: Option<Arc<jwt::JwtKeys>>,
+    role_permissions: Arc<RolePermissions>,
+    role_graph: Arc<RoleGraph>,
+    roles: Arc<RoleRegistry>,
+}
+
+impl<S: ?Sized> Clone for Backend<S> {
+    fn clone(&self) -> Self {
+        Self {
+            store: Arc::clone(&self.store),
+            webauthn: self.webauthn.clone(),
+            jwt: self.jwt.clone(),
+            role_permissions: Arc::clone(&self.role_permissions),
+            role_graph: Arc::clone(&self.role_graph),
+            roles: Arc::clone(&self.roles),
+        }
+    }
+}
+
+impl<S: ?Sized> std::fmt::Debug for Backend<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Backend").finish_non_exhaustive()
+    }
+}
+
+impl<S: UserStore + ?Sized> Backend<S> {
+    pub fn new(store: Arc<S>) -> Self {
+        Self {
+            store,
+            webauthn: None,
+            jwt: None,
+            role_permissions: Arc::new(RolePermissions::default()),
+            role_graph: Arc::new(RoleGraph::default()),
+            roles: Arc::new(RoleRegistry::default()),
+        }
+    }
+
+    /// Replaces the roles known by name, e.g. to add those of the application.
+    pub fn with_roles(mut self, roles: RoleRegistry) -> Self {
+        self.roles = Arc::new(roles);
+        self
+    }
+
+    /// The roles known by name, for forms and lists of roles.
+    pub fn roles(&self) -> &RoleRegistry {
+        &self.roles
+    }
+
+    /// The named permissions some role grants, for forms and lists of permissions.
+    pub fn permission_names(&self) -> Vec<String> {
+        self.role_permissions.names()
+    }
+
+    /// Replaces which roles include which, see [`RoleGraph::from_config`].
+    pub fn with_role_graph(mut self, role_graph: RoleGraph) -> Self {
+        self.role_graph = Arc::new(role_graph);
+        self
+    }
+
+    /// Replaces the permissions granted by each role, see [`RolePermissions::from_config`].
+    pub fn with_role_permissions(mut self, role_permissions: RolePermissions) -> Self {
+        self.role_permissions = Arc::new(role_permissions);
+        self
+    }
+
+    /// Enables passkeys, see [`passkey::webauthn`].
+    pub fn with_webauthn(mut self, webauthn: webauthn_rs::Webauthn) -> Self {
+        self.webauthn = Some(Arc::new(webauthn));
+        self
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    fn webauthn(&self) -> Result<&webauthn_rs::Webauthn, Error> {
+        self.webauthn.as_deref().ok_or(Error::PasskeysDisabled)
+    }
+
+    /// Enables JWT sessions, see [`jwt::JwtKeys::from_config`].
+    pub fn with_jwt(mut self, keys: jwt::JwtKeys) -> Self {
+        self.jwt = Some(Arc::new(keys));
+        self
+    }
+
+    pub fn jwt_enabled(&self) -> bool {
+        self.jwt.is_some()
+    }
+
+    fn jwt(&self) -> Result<&jwt::JwtKeys, Error> {
+        self.jwt.as_deref().ok_or(Error::JwtDisabled)
+    }
+
+    /// Creates a new account after checking the username and password against
+    /// [`validate_username`] and [`validate_password`].
+    pub async fn register_user(
+        &self,
+        username: &str,
+        password: &str,
+        roles: &[u8],
+    ) -> Result<User, Error> {
+        validate_username(username)?;
+        validate_password(username, password)?;
+
+        let password = password.to_owned();
+        let pw_hash = tokio::task::spawn_blocking(move || hash_password(&password)).await??;
+
+        let user = User {
+            id: UserId(uuid::Uuid::new_v4().to_string()),
+            username: username.into(),
+            pw_hash,
+            roles: roles.to_vec(),
+            verified: false,
+            totp_secret: None,
+            identities: Vec::new(),
+        };
+        self.store.insert_user(&user).await?;
+        Ok(user)
+    }
+
+    /// Replaces the password of a user, which also logs out all of their sessions
+    /// and revokes their refresh tokens, password reset links and login links.
+    pub async fn set_password(&self, user: &User, password: &str) -> Result<(), Error> {
+        validate_password(&user.username, password)?;
+
+        let password = password.to_owned();
+        let pw_hash = tokio::task::spawn_blocking(move || hash_password(&password)).await??;
+        self.store.update_password(&user.id.0, &pw_hash).await?;
+        for kind in [
+            TokenKind::Refresh,
+            TokenKind::PasswordReset,
+            TokenKind::MagicLogin,
+        ] {
+            self.store.delete_tokens(&user.id.0, kind).await?;
+        }
+        Ok(())
+    }
+
+    /// Stores a new single-use token for `user_id` and returns the value to hand out.
+    pub async fn issue_token(
+        &self,
+        user_id: &str,
+        kind: TokenKind,
+        ttl: time::Duration,
+    ) -> Result<String, Error> {
+        let (token, hash) = tokens::generate_token();
+        self.store
+            .insert_token(&Token {
+                hash,
+                kind,
+                user_id: user_id.into(),
+                expires_at: time::OffsetDateTime::now_utc() + ttl,
+            })
+            .await?;
+        Ok(token)
+    }
+
+    /// Returns the owner of a valid token without consuming it.
+    pub async fn check_token(&self, kind: TokenKind, token: &str) -> Result<User, Error> {
+        let token = self
+            .store
+            .get_token(kind, &tokens::hash_token(token))
+            .await?
+            .filter(|token| !token.is_expired())
+            .ok_or(Error::InvalidToken)?;
+        self.store
+            .get_user(&token.user_id)
+            .await?
+            .ok_or(Error::InvalidToken)
+    }
+
+    /// Consumes a token and returns its owner. A token can only be redeemed once.
+    pub async fn redeem_token(&self, kind: TokenKind, token: &str) -> Result<User, Error> {
+        let token = self
+            .store
+            .take_token(kind, &tokens::hash_token(token))
+            .await?
+            .filter(|token| !token.is_expired())
+            .ok_or(Error::InvalidToken)?;
+        self.store
+            .get_user(&token.user_id)
+            .await?
+            .ok_or(Error::InvalidToken)
+    }
+
+    /// Creates an API key for `user` and returns it with its stored record.
+    /// Only the hash is stored, this is the one chance to show the key.
+    ///
+    /// With `scopes` the key is a personal access token, limited to those named
+    /// permissions. `user` must have each of them.
+    pub async fn create_api_key(
+        &self,
+        user: &User,
+        name: &str,
+        scopes: Option<HashSet<String>>,
+    ) -> Result<(String, ApiKey), Error> {
+        if let Some(scopes) = &scopes {
+            let held = self.get_all_permissions(user).await?;
+            if !scopes
+                .iter()
+                .all(|scope| held.contains(&Permission::from(scope.clone())))
+            {
+                return Err(Error::ScopeNotGranted);
+            }
+        }
+
+        let (key, hash) = api_keys::generate_key();
+        let api_key = ApiKey {
+            id: uuid::Uuid::new_v4().to_string(),
+            user_id: user.id.0.clone(),
+            name: name.into(),
+            hash,
+            scopes,
+            created_at: time::OffsetDateTime::now_utc(),
+            last_used_at: None,
+            revoked_at: None,
+        };
+        self.store.insert_api_key(&api_key).await?;
+        Ok((key, api_key))
+    }
+
+    /// Creates a group whose members get `roles` and `permissions` on top of their own.
+    pub async fn create_group(
+        &self,
+        name: &str,
+        roles: &[u8],
+        permissions: &[String],
+    ) -> Result<Group, Error> {
+        let name = name.trim();
+        if !GROUP_NAME_LEN.contains(&name.chars().count()) {
+            return Err(Error::InvalidGroupName("must be 1 to 64 characters long"));
+        }
+
+        let group = Group {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: name.into(),
+            roles: roles.to_vec(),
+            permissions: permissions.to_vec(),
+            members: Vec::new(),
+        };
+        self.store.insert_group(&group).await?;
+        Ok(group)
+    }
+
+    /// Returns the owner of a valid, unrevoked API key along with the key, and records
+    /// its use. Keys only authenticate single requests, see
+    /// [`crate::middlewares::require_api_key`], never a session, which could not keep the
+    /// scopes of the key.
+    pub async fn authenticate_api_key(&self, key: &str) -> Result<Option<(User, ApiKey)>, Error> {
+        let api_key = self
+            .store
+            .get_api_key_by_hash(&api_keys::hash_key(key))
+            .await?
+            .filter(|api_key| !api_key.is_revoked());
+        let Some(api_key) = api_key else {
+            return Ok(None);
+        };
+        self.store
+            .touch_api_key(&api_key.id, time::OffsetDateTime::now_utc())
+            .await?;
+        let user = self.store.get_user(&api_key.user_id).await?;
+        Ok(user.map(|user| (user, api_key)))
+    }
+
+    /// The roles of `user` along with those of their groups and every role these
+    /// inherit from, see [`RoleGraph::closure`].
+    pub async fn effective_roles(&self, user: &User) -> Result<HashSet<u8>, Error> {
+        let mut roles = user.roles.clone();
+        for group in self.store.get_user_groups(&user.id.0).await? {
+            roles.extend(group.roles);
+        }
+        Ok(self.role_graph.closure(roles))
+    }
+
+    /// Whether `user` has `role`, directly or through a role inheriting it, e.g. admins
+    /// have [`Role::User`] too.
+    pub async fn has_role(&self, user: &User, role: u8) -> Result<bool, Error> {
+        Ok(self.effective_roles(user).await?.contains(&role))
+    }
+
+    /// The permissions a request of `user` limited to the named permissions in `scopes`
+    /// acts with: those of them `user` still has. Holding a role is not among them, so
+    /// scoped requests fail role checks and cannot hand out roles.
+    pub async fn scoped_permissions(
+        &self,
+        user: &User,
+        scopes: &HashSet<String>,
+    ) -> Result<HashSet<Permission>, Error> {
+        let held = self.get_all_permissions(user).await?;
+        Ok(scopes
+            .iter()
+            .cloned()
+            .map(Permission::from)
+            .filter(|scope| held.contains(scope))
+            .collect())
+    }
+
+    /// The named permissions `user` has through their roles and groups, sorted, e.g. to
+    /// pick the scopes of an API key from.
+    pub async fn named_permissions(&self, user: &User) -> Result<Vec<String>, Error> {
+        let mut names: Vec<_> = self
+            .get_all_permissions(user)
+            .await?
+            .into_iter()
+            .filter_map(|perm| match perm {
+                Permission::Named(name) => Some(name.into_owned()),
+                Permission::Role(_) => None,
+            })
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    /// Whether `user` may hand out `roles` and `permissions`, e.g. to a group they
+    /// manage: only what they have themselves, as limited by `scopes` for a scoped token.
+    pub async fn can_grant(
+        &self,
+        user: &User,
+        scopes: Option<&HashSet<Permission>>,
+        roles: &[u8],
+        permissions: &[String],
+    ) -> Result<bool, Error> {
+        let held = self.get_all_permissions(user).await?;
+        let wanted = roles
+            .iter()
+            .map(|role| Permission::Role(*role))
+            .chain(permissions.iter().cloned().map(Permission::from));
+        Ok(wanted
+            .into_iter()
+            .all(|perm| held.contains(&perm) && scopes.is_none_or(|scopes| scopes.contains(&perm))))
+    }
+
+    /// Issues an access token for `user` and a refresh token to renew it.
+    pub async fn issue_jwt(&self, user: &User) -> Result<jwt::TokenPair, Error> {
+        let keys = self.jwt()?;
+        let access_token = keys.encode(user)?;
+        let refresh_token = self
+            .issue_token(&user.id.0, TokenKind::Refresh, keys.refresh_ttl())
+            .await?;
+        Ok(jwt::TokenPair {
+            access_token,
+            refresh_token,
+            expires_in: keys.access_ttl(),
+        })
+    }
+
+    /// Trades a refresh token for a new pair. Refresh tokens are single-use, and the
+    /// user is read again so role changes show up in the new access token.
+    pub async fn refresh_jwt(&self, refresh_token: &str) -> Result<jwt::TokenPair, Error> {
+        self.jwt()?;
+        let user = self.redeem_token(TokenKind::Refresh, refresh_token).await?;
+        self.issue_jwt(&user).await
+    }
+
+    /// Revokes a refresh token. Its access tokens stay valid until they expire.
+    pub async fn revoke_refresh_token(&self, refresh_token: &str) -> Result<(), Error> {
+        self.store
+            .take_token(TokenKind::Refresh, &tokens::hash_token(refresh_token))
+            .await?;
+        Ok(())
+    }
+
+    /// Revokes every refresh token of `user_id`, e.g. to log out all of their clients.
+    pub async fn revoke_refresh_tokens(&self, user_id: &str) -> Result<(), Error> {
+        self.store.delete_tokens(user_id, TokenKind::Refresh).await
+    }
+
+    /// Checks an access token without a store lookup, see [`jwt::JwtKeys::decode`].
+    pub fn authenticate_jwt(&self, access_token: &str) -> Result<User, Error> {
+        self.jwt()?.decode(access_token)
+    }
+
+    /// Checks a code of the authenticator app of `user`, see [`Backend::verify_second_factor`].
+    pub async fn verify_totp(&self, user: &User, code: &str) -> Result<bool, Error> {
+        self.check_second_factor(user, code, false).await
+    }
+
+    /// Checks a code of the authenticator app of `user` or one of their recovery codes,
+    /// which is consumed. Each authenticator code is only accepted once. After
+    /// [`MAX_SECOND_FACTOR_FAILURES`] failures in a row further attempts fail with
+    /// [`Error::SecondFactorLocked`] for [`SECOND_FACTOR_LOCKOUT`], however many
+    /// sessions they come from.
+    pub async fn verify_second_factor(&self, user: &User, code: &str) -> Result<bool, Error> {
+        self.check_second_factor(user, code, true).await
+    }
+
+    async fn check_second_factor(
+        &self,
+        user: &User,
+        code: &str,
+        recovery_codes: bool,
+    ) -> Result<bool, Error> {
+        let user_id = &user.id.0;
+        let now = time::OffsetDateTime::now_utc();
+        let locked_until = self.store.get_second_factor_lock(user_id).await?;
+        if locked_until.is_some_and(|until| until > now) {
+            return Err(Error::SecondFactorLocked);
+        }
+
+        let step = user
+            .totp_secret
+            .as_deref()
+            .and_then(|secret| totp::verify_at(secret, code, now.unix_timestamp()));
+        let verified = match step {
+            Some(step) => self.store.use_totp_step(user_id, step).await?,
+            None => false,
+        };
+        let verified =
+            verified || (recovery_codes && self.take_recovery_code(user_id, code).await?);
+
+        if verified {
+            self.store
+                .reset_second_factor_failures(user_id, None)
+                .await?;
+        } else if self.store.add_second_factor_failure(user_id).await? >= MAX_SECOND_FACTOR_FAILURES
+        {
+            self.store
+                .reset_second_factor_failures(user_id, Some(now + SECOND_FACTOR_LOCKOUT))
+                .await?;
+        }
+        Ok(verified)
+    }
+
+    /// Replaces the recovery codes of a user with a new set and returns them.
+    /// Only their hashes are stored, this is the one chance to show them.
+    pub async fn regenerate_recovery_codes(&self, user_id: &str) -> Result<Vec<String>, Error> {
+        let codes = recovery::generate_codes();
+        // Argon2 is CPU bound, keep it off the async runtime
+        let hashes: Vec<String> = tokio::task::spawn_blocking({
+            let codes = codes.clone();
+            move || {
+                codes
+                    .iter()
+                    .map(|code| recovery::hash_code(code))
+                    .collect::<Result<_, _>>()
+            }
+        })
+        .await??;
+        self.store.replace_recovery_codes(user_id, &hashes).await?;
+        Ok(codes)
+    }
+
+    /// Consumes the recovery code `code` of `user_id`, returns whether it was one.
+    async fn take_recovery_code(&self, user_id: &str, code: &str) -> Result<bool, Error> {
+        let hashes = self.store.get_recovery_codes(user_id).await?;
+        let code = code.to_string();
+        // The hashes are salted, so each one has to be checked
+        let matched = tokio::task::spawn_blocking(move || {
+            for hash in hashes {
+                if recovery::verify_code(&code, &hash)? {
+                    return Ok::<_, Error>(Some(hash));
+                }
+            }
+            Ok(None)
+        })
+        .await??;
+        match matched {
+            Some(hash) => self.store.take_recovery_code(user_id, &hash).await,
+            None => Ok(false),
+        }
+    }
+
+    /// Returns the challenge for registering a new passkey and the state to keep
+    /// until [`Backend::finish_passkey_registration`].
+    pub async fn start_passkey_registration(
+        &self,
+        user: &User,
+    ) -> Result<
+        (
+            passkey::CreationChallengeResponse,
+            passkey::PasskeyRegistration,
+        ),
+        Error,
+    > {
+        let webauthn = self.webauthn()?;
+        // Keeps an authenticator from being registered twice
+        let exclude = self
+            .store
+            .get_passkeys(&user.id.0)
+            .await?
+            .into_iter()
+            .map(|stored| stored.passkey.cred_id().clone())
+            .collect();
+        Ok(webauthn.start_passkey_registration(
+            passkey::user_handle(&user.id.0),
+            &user.username,
+            &user.username,
+            Some(exclude),
+        )?)
+    }
+
+    /// Verifies the authenticator's answer and stores the new passkey.
+    pub async fn finish_passkey_registration(
+        &self,
+        user: &User,
+        name: &str,
+        credential: &passkey::RegisterPublicKeyCredential,
+        state: &passkey::PasskeyRegistration,
+    ) -> Result<StoredPasskey, Error> {
+        let passkey = self
+            .webauthn()?
+            .finish_passkey_registration(credential, state)?;
+        let stored = StoredPasskey {
+            id: passkey::credential_id(passkey.cred_id()),
+            user_id: user.id.0.clone(),
+            name: name.into(),
+            passkey,
+            created_at: time::OffsetDateTime::now_utc(),
+        };
+        self.store.insert_passkey(&stored).await?;
+        Ok(stored)
+    }
+
+    /// Returns a login challenge for the passkeys of a user and the state to pass
+    /// back in [`Credentials::Passkey`], or `None` if they have no passkey.
+    pub async fn start_passkey_authentication(
+        &self,
+        user_id: &str,
+    ) -> Result<
+        Option<(
+            passkey::RequestChallengeResponse,
+            passkey::PasskeyAuthentication,
+        )>,
+        Error,
+    > {
+        let webauthn = self.webauthn()?;
+        let passkeys: Vec<_> = self
+            .store
+            .get_passkeys(user_id)
+            .await?
+            .into_iter()
+            .map(|stored| stored.passkey)
+            .collect();
+        if passkeys.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(webauthn.start_passkey_authentication(&passkeys)?))
+    }
+
+    /// Returns the user linked to an identity at `provider`, creating one with `roles`
+    /// on the first login. The account has no password and counts as verified, the
+    /// provider vouches for it. Creating it fails with [`Error::UsersReadOnly`] when the
+    /// users come from an htpasswd file, see [`htpasswd`].
+    pub async fn find_or_provision_external_user(
+        &self,
+        provider: &str,
+        claims: &oidc::IdTokenClaims,
+        roles: &[u8],
+    ) -> Result<User, Error> {
+        if let Some(user) = self
+            .store
+            .get_user_by_identity(provider, &claims.sub)
+            .await?
+        {
+            return Ok(user);
+        }
+
+        let base = oidc::username_hint(claims);
+        for attempt in 0..10 {
+            let username = match attempt {
+                0 => base.clone(),
+                _ => format!("{base}{}", rand::random::<u16>() % 1000),
+            };
+            let mut user = User {
+                id: UserId(uuid::Uuid::new_v4().to_string()),
+                username,
+                pw_hash: String::new(),
+                roles: roles.to_vec(),
+                verified: true,
+                totp_secret: None,
+                identities: Vec::new(),
+            };
+            match self.store.insert_user(&user).await {
+                Ok(()) => {}
+                Err(Error::UsernameTaken) => continue,
+                Err(err) => return Err(err),
+            }
+
+            let identity = ExternalIdentity {
+                provider: provider.into(),
+                subject: claims.sub.clone(),
+                user_id: user.id.0.clone(),
+                created_at: time::OffsetDateTime::now_utc(),
+            };
+            if let Err(err) = self.store.insert_identity(&identity).await {
+                // A concurrent first login provisioned the identity in the meantime
+                self.store.delete_user(&user.id.0).await?;
+                return self
+                    .store
+                    .get_user_by_identity(provider, &claims.sub)
+                    .await?
+                    .ok_or(err);
+            }
+            user.identities.push(identity);
+            return Ok(user);
+        }
+        Err(Error::UsernameTaken)
+    }
+
+    /// Returns the user a trusted proxy named, see [`proxy::ProxyAuth`]. Unknown users are
+    /// created with `roles` if given, without a password since the proxy logs them in.
+    /// Creating them fails with [`Error::UsersReadOnly`] when the users come from an
+    /// htpasswd file, see [`htpasswd`].
+    pub async fn find_or_provision_proxy_user(
+        &self,
+        username: &str,
+        roles: Option<&[u8]>,
+    ) -> Result<Option<User>, Error> {
+        if let Some(user) = self.store.get_user_by_username(username).await? {
+            return Ok(Some(user));
+        }
+        let Some(roles) = roles else {
+            return Ok(None);
+        };
+        validate_username(username)?;
+
+        let user = User {
+            id: UserId(uuid::Uuid::new_v4().to_string()),
+            username: username.into(),
+            pw_hash: String::new(),
+            roles: roles.to_vec(),
+            verified: true,
+            totp_secret: None,
+            identities: Vec::new(),
+        };
+        match self.store.insert_user(&user).await {
+            Ok(()) => Ok(Some(user)),
+            // A concurrent first request provisioned the user in the meantime
+            Err(Error::UsernameTaken) => self.store.get_user_by_username(username).await,
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Every way `user` can log in.
+    pub async fn login_methods(&self, user: &User) -> Result<Vec<LoginMethod>, Error> {
+        let mut methods = Vec::new();
+        if user.has_password() {
+            methods.push(LoginMethod::Password);
+        }
+        methods.extend(
+            user.identities
+                .iter()
+                .map(|identity| LoginMethod::External {
+                    provider: identity.provider.clone(),
+                    subject: identity.subject.clone(),
+                }),
+        );
+        methods.extend(
+            self.store
+                .get_passkeys(&user.id.0)
+                .await?
+                .into_iter()
+                .map(|passkey| LoginMethod::Passkey { id: passkey.id }),
+        );
+        Ok(methods)
+    }
+
+    /// Links an identity at `provider` to `user`. Fails with [`Error::IdentityInUse`]
+    /// if it already belongs to someone else.
+    pub async fn link_external_identity(
+        &self,
+        user: &User,
+        provider: &str,
+        subject: &str,
+    ) -> Result<(), Error> {
+        if let Some(owner) = self.store.get_user_by_identity(provider, subject).await? {
+            if owner.id.0 != user.id.0 {
+                return Err(Error::IdentityInUse);
+            }
+            return Ok(());
+        }
+        self.store
+            .insert_identity(&ExternalIdentity {
+                provider: provider.into(),
+                subject: subject.into(),
+                user_id: user.id.0.clone(),
+                created_at: time::OffsetDateTime::now_utc(),
+            })
+            .await
+    }
+
+    /// Removes a login method of `user`. The last one is refused with
+    /// [`Error::LastLoginMethod`], so the account can't lock itself out.
+    /// Removing the password changes [`AuthUser::session_auth_hash`], log the user in again.
+    pub async fn unlink_login_method(
+        &self,
+        user: &User,
+        method: &LoginMethod,
+    ) -> Result<(), Error> {
+        // The store checks and removes in one go, so two removals at once cannot take
+        // away the last two methods
+        if !self.store.delete_login_method(&user.id.0, method).await? {
+            return Err(Error::UnknownLoginMethod);
+        }
+        Ok(())
+    }
+
+    /// Marks the owner of a [`TokenKind::AccountVerification`] token as verified.
+    pub async fn verify_account(&self, token: &str) -> Result<User, Error> {
+        let mut user = self
+            .redeem_token(TokenKind::AccountVerification, token)
+            .await?;
+        self.store.set_verified(&user.id.0, true).await?;
+        user.verified = true;
+        Ok(user)
+    }
+
+    /// Sets a new password using a [`TokenKind::PasswordReset`] token. The token is
+    /// only consumed once the new password passed validation.
+    pub async fn reset_password(&self, token: &str, password: &str) -> Result<User, Error> {
+        let user = self.check_token(TokenKind::PasswordReset, token).await?;
+        validate_password(&user.username, password)?;
+
+        let user = self.redeem_token(TokenKind::PasswordReset, token).await?;
+        self.set_password(&user, password).await?;
+        Ok(user)
+    }
+}
+
+const USERNAME_LEN: std::ops::RangeInclusive<usize> = 3..=32;
+const GROUP_NAME_LEN: std::ops::RangeInclusive<usize> = 1..=64;
+// Argon2 accepts longer passwords, the upper bound only keeps hashing cheap to request
+const PASSWORD_LEN: std::ops::RangeInclusive<usize> = 8..=128;
+
+pub fn validate_username(username: &str) -> Result<(), Error> {
+    if !USERNAME_LEN.contains(&username.chars().count()) {
+        return Err(Error::InvalidUsername("must be 3 to 32 characters long"));
+    }
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+    {
+        return Err(Error::InvalidUsername(
+            "only letters, digits, '_', '-' and '.' are allowed",
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_password(username: &str, password: &str) -> Result<(), Error> {
+    if !PASSWORD_LEN.contains(&password.chars().count()) {
+        return Err(Error::WeakPassword("must be 8 to 128 characters long"));
+    }
+    if password.to_lowercase().contains(&username.to_lowercase()) {
+        return Err(Error::WeakPassword("must not contain the username"));
+    }
+    if password.chars().all(|c| c.is_alphabetic()) || password.chars().all(|c| c.is_numeric()) {
+        return Err(Error::WeakPassword(
+            "must mix letters with digits or symbols",
+        ));
+    }
+    Ok(())
+}
+
+/// Hashes `password` with Argon2id and a random salt, returning the PHC string.
+pub fn hash_password(password: &str) -> Result<String, Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)?
+        .to_string())
+}
+
+/// Checks `password` against a PHC string. The digest comparison is constant time.
+pub fn verify_password(password: &str, pw_hash: &str) -> Result<bool, Error> {
+    // Only htpasswd files bring bcrypt hashes, new ones are always Argon2
+    if pw_hash.starts_with("$2") {
+        return Ok(bcrypt::verify(password, pw_hash)?);
+    }
+    let parsed_hash = PasswordHash::new(pw_hash)?;
+    match Argon2::default().verify_password(password.as_bytes(), &parsed_hash) {
+        Ok(()) => Ok(true),
+        Err(password_hash::Error::Password) => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+// Verified against when the username is unknown, so a miss costs the same as a wrong password.
+fn dummy_hash() -> &'static str {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    DUMMY_HASH
+        .get_or_init(|| hash_password("dummy password").expect("Failed to hash dummy password"))
+}
+
+#[async_trait]
+impl<S: UserStore + ?Sized> AuthnBackend for Backend<S> {
+    type User = User;
+    type Credentials = Credentials;
+    type Error = Error;
+
+    async fn authenticate(
+        &self,
+        creds: Self::Credentials,
+    ) -> Result<Option<Self::User>, Self::Error> {
+        match creds {
+            Credentials::Password { username, password } => {
+                let user = self.store.get_user_by_username(&username).await?;
+
+                // Argon2 is CPU bound, keep it off the async runtime
+                tokio::task::spawn_blocking(move || match user {
+                    Some(user) if user.has_password() => {
+                        Ok(verify_password(&password, &user.pw_hash)?.then_some(user))
+                    }
+                    _ => {
+                        let _ = verify_password(&password, dummy_hash());
+                        Ok(None)
+                    }
+                })
+                .await?
+            }
+            Credentials::Passkey {
+                user_id,
+                credential,
+                state,
+            } => {
+                // The state only allows the passkeys of `user_id`
+                let Ok(result) = self
+                    .webauthn()?
+                    .finish_passkey_authentication(&credential, &state)
+                else {
+                    return Ok(None);
+                };
+                let Some(user) = self.store.get_user(&user_id).await? else {
+                    return Ok(None);
+                };
+
+                // Persist the signature counter, a cloned authenticator falls behind it
+                if result.needs_update() {
+                    let id = passkey::credential_id(result.cred_id());
+                    let stored = self.store.get_passkeys(&user_id).await?;
+                    if let Some(mut stored) = stored.into_iter().find(|stored| stored.id == id) {
+                        stored.passkey.update_credential(&result);
+                        self.store.update_passkey(&stored).await?;
+                    }
+                }
+                Ok(Some(user))
+            }
+        }
+    }
+
+    async fn get_user(
+        &self,
+        user_id: &axum_login::UserId<Self>,
+    ) -> Result<Option<Self::User>, Self::Error> {
+        self.store.get_user(user_id).await
+    }
+}
+
+#[async_trait]
+impl<S: UserStore + ?Sized> AuthzBackend for Backend<S> {
+    type Permission = Permission;
+
+    async fn get_user_permissions(
+        &self,
+        user: &Self::User,
+    ) -> Result<HashSet<Self::Permission>, Self::Error> {
+        let roles = self.role_graph.closure(user.roles.iter().copied());
+        Ok(self.role_permissions.granted_by(roles))
+    }
+
+    async fn get_group_permissions(
+        &self,
+        user: &Self::User,
+    ) -> Result<HashSet<Self::Permission>, Self::Error> {
+        let groups = self.store.get_user_groups(&user.id.0).await?;
+        let roles = self
+            .role_graph
+            .closure(groups.iter().flat_map(|group| group.roles.iter().copied()));
+        let mut permissions = self.role_permissions.granted_by(roles);
+        permissions.extend(
+            groups
+                .into_iter()
+                .flat_map(|group| group.permissions)
+                .map(Permission::from),
+        );
+        Ok(permissions)
+    }
+
+    async fn get_all_permissions(
+        &self,
+        user: &Self::User,
+    ) -> Result<HashSet<Self::Permission>, Self::Error> {
+        let mut all_perms = HashSet::new();
+        all_perms.extend(self.get_user_permissions(user).await?);
+        all_perms.extend(self.get_group_permissions(user).await?);
+        Ok(all_perms)
+    }
+
+    async fn has_perm(
+        &self,
+        user: &Self::User,
+        perm: Self::Permission,
+    ) -> Result<bool, Self::Error> {
+        Ok(self.get_all_permissions(user).await?.contains(&perm))
+    }
+}
@@ -0,0 +1,263 @@
+//! OpenID Connect login with the authorization code flow and PKCE. Endpoints and signing
+//! keys are discovered from the issuer, so any compliant provider works, a local mock included.
+
+use super::{tokens, Error};
+use crate::config::OidcProviderConfig;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tokio::sync::{OnceCell, RwLock};
+
+/// An account at an external provider, linked to a local user.
+#[derive(Debug, Clone)]
+pub struct ExternalIdentity {
+    /// [`OidcProviderConfig::name`] of the provider
+    pub provider: String,
+    /// The `sub` claim, stable for a user at a given provider
+    pub subject: String,
+    pub user_id: String,
+    pub created_at: OffsetDateTime,
+}
+
+/// What to keep in the session between [`OidcProvider::authorization_url`]
+/// and [`OidcProvider::exchange_code`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthorizationRequest {
+    pub provider: String,
+    pub state: String,
+    pub nonce: String,
+    pub pkce_verifier: String,
+    /// Links the identity to the logged in user instead of logging in with it
+    pub link: bool,
+}
+
+/// Claims of a validated ID token.
+#[derive(Debug, Clone, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub nonce: Option<String>,
+    pub preferred_username: Option<String>,
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// A configured provider. Its metadata is fetched on first use and the signing keys
+/// are refetched when a token names an unknown one, to follow key rotation.
+#[derive(Debug)]
+pub struct OidcProvider {
+    config: OidcProviderConfig,
+    redirect_uri: String,
+    http: reqwest::Client,
+    metadata: OnceCell<ProviderMetadata>,
+    jwks: RwLock<JwkSet>,
+}
+
+impl OidcProvider {
+    pub fn new(config: OidcProviderConfig, public_url: &str, http: reqwest::Client) -> Self {
+        let redirect_uri = format!(
+            "{}/login/oidc/{}/callback",
+            public_url.trim_end_matches('/'),
+            config.name
+        );
+        Self {
+            config,
+            redirect_uri,
+            http,
+            metadata: OnceCell::new(),
+            jwks: RwLock::new(JwkSet { keys: Vec::new() }),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    pub fn display_name(&self) -> &str {
+        &self.config.display_name
+    }
+
+    async fn metadata(&self) -> Result<&ProviderMetadata, Error> {
+        self.metadata
+            .get_or_try_init(|| async {
+                let issuer = self.config.issuer.trim_end_matches('/');
+                let metadata: ProviderMetadata = self
+                    .http
+                    .get(format!("{issuer}/.well-known/openid-configuration"))
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                if metadata.issuer.trim_end_matches('/') != issuer {
+                    return Err(Error::Oidc("discovered issuer does not match the config"));
+                }
+                Ok(metadata)
+            })
+            .await
+    }
+
+    /// Returns the URL to send the browser to, and the request to check the callback against.
+    pub async fn authorization_url(&self) -> Result<(String, AuthorizationRequest), Error> {
+        let metadata = self.metadata().await?;
+        let request = AuthorizationRequest {
+            provider: self.config.name.clone(),
+            state: tokens::generate_token().0,
+            nonce: tokens::generate_token().0,
+            pkce_verifier: tokens::generate_token().0,
+            link: false,
+        };
+        // S256 challenge, the SHA-256 of the verifier in base64url
+        let pkce_challenge = tokens::hash_token(&request.pkce_verifier);
+
+        let url = Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", &self.config.client_id),
+                ("redirect_uri", &self.redirect_uri),
+                ("scope", &self.config.scopes.join(" ")),
+                ("state", &request.state),
+                ("nonce", &request.nonce),
+                ("code_challenge", &pkce_challenge),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|_| Error::Oidc("invalid authorization endpoint"))?;
+
+        Ok((url.into(), request))
+    }
+
+    /// Redeems the authorization code from the callback and returns the claims of the
+    /// validated ID token. The caller checks `state` before calling this.
+    pub async fn exchange_code(
+        &self,
+        request: &AuthorizationRequest,
+        code: &str,
+    ) -> Result<IdTokenClaims, Error> {
+        let metadata = self.metadata().await?;
+        let mut token_request = self.http.post(&metadata.token_endpoint).form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.redirect_uri),
+            ("client_id", &self.config.client_id),
+            ("code_verifier", &request.pkce_verifier),
+        ]);
+        if let Some(secret) = &self.config.client_secret {
+            token_request = token_request.basic_auth(&self.config.client_id, Some(secret));
+        }
+        let response: TokenResponse = token_request
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let claims = self.validate_id_token(&response.id_token).await?;
+        if claims.nonce.as_deref() != Some(request.nonce.as_str()) {
+            return Err(Error::Oidc("ID token nonce does not match"));
+        }
+        Ok(claims)
+    }
+
+    async fn validate_id_token(&self, id_token: &str) -> Result<IdTokenClaims, Error> {
+        let header = jsonwebtoken::decode_header(id_token)?;
+        // Only asymmetric keys come from a JWKS, refusing HMAC rules out key confusion
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(Error::Oidc("ID token must be signed with a public key"));
+        }
+        let key = self.decoding_key(header.kid.as_deref()).await?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.metadata().await?.issuer]);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        Ok(jsonwebtoken::decode::<IdTokenClaims>(id_token, &key, &validation)?.claims)
+    }
+
+    async fn decoding_key(&self, kid: Option<&str>) -> Result<DecodingKey, Error> {
+        if let Some(key) = find_key(&*self.jwks.read().await, kid)? {
+            return Ok(key);
+        }
+
+        let jwks: JwkSet = self
+            .http
+            .get(&self.metadata().await?.jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let key = find_key(&jwks, kid)?;
+        *self.jwks.write().await = jwks;
+        key.ok_or(Error::Oidc("ID token is signed with an unknown key"))
+    }
+}
+
+fn find_key(jwks: &JwkSet, kid: Option<&str>) -> Result<Option<DecodingKey>, Error> {
+    let jwk = match kid {
+        Some(kid) => jwks.find(kid),
+        // Without a key id the set must be unambiguous
+        None if jwks.keys.len() == 1 => jwks.keys.first(),
+        None => None,
+    };
+    Ok(jwk.map(DecodingKey::from_jwk).transpose()?)
+}
+
+/// The providers from the config, looked up by name.
+#[derive(Debug, Default)]
+pub struct OidcProviders(Vec<OidcProvider>);
+
+impl OidcProviders {
+    pub fn from_config(providers: &[OidcProviderConfig], public_url: &str) -> Self {
+        let http = reqwest::Client::new();
+        Self(
+            providers
+                .iter()
+                .map(|config| OidcProvider::new(config.clone(), public_url, http.clone()))
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, name: &str) -> Option<&OidcProvider> {
+        self.0.iter().find(|provider| provider.name() == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &OidcProvider> {
+        self.0.iter()
+    }
+}
+
+/// A valid local username derived from the claims, collisions are handled by the caller.
+pub fn username_hint(claims: &IdTokenClaims) -> String {
+    let candidate = claims
+        .preferred_username
+        .as_deref()
+        .or_else(|| claims.email.as_deref()?.split('@').next())
+        .unwrap_or_default();
+    let mut username: String = candidate
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+        .take(28)
+        .collect();
+    if username.len() < 3 {
+        username.insert_str(0, "user");
+    }
+    username
+}
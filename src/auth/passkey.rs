@@ -0,0 +1,54 @@
+//! WebAuthn passkeys. The ceremonies only deal with serializable values, so they can be
+//! driven by a software authenticator as well as by `navigator.credentials` in a browser.
+//! `tests/passkey.rs` does the former.
+
+use super::Error;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use time::OffsetDateTime;
+use webauthn_rs::prelude::{CredentialID, Url, Uuid};
+use webauthn_rs::{Webauthn, WebauthnBuilder};
+
+pub use webauthn_rs::prelude::{
+    CreationChallengeResponse, Passkey, PasskeyAuthentication, PasskeyRegistration,
+    PublicKeyCredential, RegisterPublicKeyCredential, RequestChallengeResponse,
+};
+
+/// A passkey registered by a user.
+#[derive(Debug, Clone)]
+pub struct StoredPasskey {
+    /// Base64url credential id, see [`credential_id`]
+    pub id: String,
+    pub user_id: String,
+    /// Label picked by the user, e.g. the device it lives on
+    pub name: String,
+    /// Public key and signature counter, updated after each login
+    pub passkey: Passkey,
+    pub created_at: OffsetDateTime,
+}
+
+/// Builds the relying party for `origin`. The relying party id defaults to the host
+/// of `origin`, passkeys are bound to it and stop working if it changes.
+pub fn webauthn(origin: &str, rp_id: Option<&str>, rp_name: &str) -> Result<Webauthn, Error> {
+    let origin = Url::parse(origin).map_err(|_| Error::InvalidConfig("public_url is not a URL"))?;
+    let rp_id = match rp_id {
+        Some(rp_id) => rp_id.to_owned(),
+        None => origin
+            .host_str()
+            .ok_or(Error::InvalidConfig("public_url has no host"))?
+            .to_owned(),
+    };
+    Ok(WebauthnBuilder::new(&rp_id, &origin)?
+        .rp_name(rp_name)
+        .build()?)
+}
+
+pub fn credential_id(id: &CredentialID) -> String {
+    URL_SAFE_NO_PAD.encode(id)
+}
+
+// WebAuthn wants a UUID user handle, older accounts may have other ids
+pub(super) fn user_handle(user_id: &str) -> Uuid {
+    Uuid::parse_str(user_id)
+        .unwrap_or_else(|_| Uuid::new_v5(&Uuid::NAMESPACE_OID, user_id.as_bytes()))
+}
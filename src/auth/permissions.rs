@@ -0,0 +1,113 @@
+//! Named permissions, e.g. `reports:read`. Roles grant bundles of them, see
+//! [`RolePermissions`], and the `require_permission` middleware checks for one. Holding
+//! a role is a permission too, see [`Permission::Role`].
+
+use super::roles::RoleRegistry;
+use super::{Error, Role};
+use crate::config::RoleConfig;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// What a user may do.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Permission {
+    /// Granted by the bundles of roles, by convention `<resource>:<action>`
+    Named(Cow<'static, str>),
+    /// Held by everyone with the role, by its id. Bundles only ever hold named
+    /// permissions, so the config cannot hand out roles this way.
+    Role(u8),
+}
+
+impl Permission {
+    pub const fn new(name: &'static str) -> Self {
+        Self::Named(Cow::Borrowed(name))
+    }
+}
+
+impl From<String> for Permission {
+    fn from(name: String) -> Self {
+        Self::Named(Cow::Owned(name))
+    }
+}
+
+impl std::fmt::Display for Permission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Named(name) => f.write_str(name),
+            Self::Role(role) => write!(f, "role {role}"),
+        }
+    }
+}
+
+/// Reading the member data.
+pub const DATA_READ: Permission = Permission::new("data:read");
+/// Reading the admin data.
+pub const SECRET_READ: Permission = Permission::new("secret:read");
+/// Creating groups and changing their roles and members.
+pub const GROUPS_MANAGE: Permission = Permission::new("groups:manage");
+
+/// The permissions each role grants.
+#[derive(Debug, Clone)]
+pub struct RolePermissions(HashMap<u8, HashSet<Permission>>);
+
+/// The bundles of the predefined roles unless the config replaces them. Roles also get
+/// the permissions of the roles they inherit from, see [`super::RoleGraph`].
+impl Default for RolePermissions {
+    fn default() -> Self {
+        Self(HashMap::from([
+            (Role::User.into(), HashSet::from([DATA_READ])),
+            (Role::Editor.into(), HashSet::new()),
+            (
+                Role::Admin.into(),
+                HashSet::from([SECRET_READ, GROUPS_MANAGE]),
+            ),
+        ]))
+    }
+}
+
+impl RolePermissions {
+    /// The default bundles, with those of the roles setting `permissions` in `roles`
+    /// replaced. Names are looked up in `registry`.
+    pub fn from_config(
+        roles: &HashMap<String, RoleConfig>,
+        registry: &RoleRegistry,
+    ) -> Result<Self, Error> {
+        let mut role_permissions = Self::default();
+        for (name, config) in roles {
+            let Some(permissions) = &config.permissions else {
+                continue;
+            };
+            let role = registry.config_role(name)?;
+            role_permissions
+                .0
+                .insert(role, permissions.iter().cloned().map(Into::into).collect());
+        }
+        Ok(role_permissions)
+    }
+
+    /// The named permissions in any bundle, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let names: BTreeSet<_> = self
+            .0
+            .values()
+            .flatten()
+            .filter_map(|perm| match perm {
+                Permission::Named(name) => Some(name.to_string()),
+                Permission::Role(_) => None,
+            })
+            .collect();
+        names.into_iter().collect()
+    }
+
+    /// Everything `roles` grant together, along with [`Permission::Role`] for each of
+    /// them. Inherited roles must be included already.
+    pub fn granted_by(&self, roles: impl IntoIterator<Item = u8>) -> HashSet<Permission> {
+        roles
+            .into_iter()
+            .flat_map(|role| {
+                let bundle = self.0.get(&role).into_iter().flatten().cloned();
+                bundle.chain([Permission::Role(role)])
+            })
+            .collect()
+    }
+}
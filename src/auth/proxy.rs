@@ -0,0 +1,155 @@
+//! Authentication by a reverse proxy that logged the user in and names them in a
+//! request header. The header is only believed from the configured proxy addresses.
+
+use super::Error;
+use crate::config::ProxyAuthConfig;
+use http::HeaderName;
+use std::net::IpAddr;
+
+/// The proxy settings from the config, ready to check requests against.
+#[derive(Debug, Clone)]
+pub struct ProxyAuth {
+    header: HeaderName,
+    trusted_proxies: Vec<IpNet>,
+    provision: bool,
+}
+
+impl ProxyAuth {
+    pub fn from_config(config: &ProxyAuthConfig) -> Result<Self, Error> {
+        let header = HeaderName::try_from(config.header.as_str())
+            .map_err(|_| Error::InvalidConfig("proxy_auth.header is not a header name"))?;
+        let trusted_proxies = config
+            .trusted_proxies
+            .iter()
+            .map(|cidr| IpNet::parse(cidr))
+            .collect::<Option<_>>()
+            .ok_or(Error::InvalidConfig(
+                "proxy_auth.trusted_proxies must be CIDRs, e.g. 10.0.0.0/8",
+            ))?;
+        Ok(Self {
+            header,
+            trusted_proxies,
+            provision: config.provision,
+        })
+    }
+
+    pub fn header(&self) -> &HeaderName {
+        &self.header
+    }
+
+    /// Whether unknown users are created on their first request.
+    pub fn provision(&self) -> bool {
+        self.provision
+    }
+
+    pub fn is_trusted(&self, peer: IpAddr) -> bool {
+        // Dual-stack listeners see IPv4 peers as IPv4-mapped IPv6 addresses
+        let peer = peer.to_canonical();
+        self.trusted_proxies.iter().any(|net| net.contains(peer))
+    }
+}
+
+/// An address range in CIDR notation.
+#[derive(Debug, Clone, Copy)]
+struct IpNet {
+    addr: IpAddr,
+    prefix_len: u32,
+}
+
+impl IpNet {
+    fn parse(cidr: &str) -> Option<Self> {
+        let (addr, prefix_len) = cidr.split_once('/')?;
+        let addr: IpAddr = addr.parse().ok()?;
+        let prefix_len: u32 = prefix_len.parse().ok()?;
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        (prefix_len <= max_len).then_some(Self { addr, prefix_len })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix_len).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix_len).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxy_auth(trusted_proxies: &[&str]) -> Result<ProxyAuth, Error> {
+        ProxyAuth::from_config(&ProxyAuthConfig {
+            trusted_proxies: trusted_proxies
+                .iter()
+                .map(|cidr| cidr.to_string())
+                .collect(),
+            ..Default::default()
+        })
+    }
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn matches_ipv4_ranges() {
+        let net = IpNet::parse("10.1.0.0/16").unwrap();
+        assert!(net.contains(ip("10.1.0.0")));
+        assert!(net.contains(ip("10.1.255.255")));
+        assert!(!net.contains(ip("10.2.0.1")));
+        assert!(!net.contains(ip("::ffff:10.1.0.1")));
+
+        assert!(IpNet::parse("0.0.0.0/0").unwrap().contains(ip("192.0.2.1")));
+        let host = IpNet::parse("192.0.2.1/32").unwrap();
+        assert!(host.contains(ip("192.0.2.1")));
+        assert!(!host.contains(ip("192.0.2.2")));
+    }
+
+    #[test]
+    fn matches_ipv6_ranges() {
+        let net = IpNet::parse("2001:db8::/32").unwrap();
+        assert!(net.contains(ip("2001:db8:ffff::1")));
+        assert!(!net.contains(ip("2001:db9::1")));
+        assert!(!net.contains(ip("32.1.13.184")));
+
+        assert!(IpNet::parse("::/0").unwrap().contains(ip("2001:db8::1")));
+        let host = IpNet::parse("::1/128").unwrap();
+        assert!(host.contains(ip("::1")));
+        assert!(!host.contains(ip("::2")));
+    }
+
+    #[test]
+    fn rejects_invalid_cidrs() {
+        for cidr in [
+            "10.0.0.0",
+            "10.0.0.0/33",
+            "::/129",
+            "10.0.0/8",
+            "10.0.0.0/-1",
+            "/8",
+        ] {
+            assert!(IpNet::parse(cidr).is_none(), "{cidr}");
+        }
+        assert!(matches!(
+            proxy_auth(&["127.0.0.1/32", "localhost/32"]),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn trusts_ipv4_mapped_peers() {
+        let proxy_auth = proxy_auth(&["127.0.0.1/32", "fd00::/8"]).unwrap();
+        assert!(proxy_auth.is_trusted(ip("127.0.0.1")));
+        assert!(proxy_auth.is_trusted(ip("::ffff:127.0.0.1")));
+        assert!(proxy_auth.is_trusted(ip("fd12::1")));
+        assert!(!proxy_auth.is_trusted(ip("127.0.0.2")));
+        assert!(!proxy_auth.is_trusted(ip("::1")));
+    }
+}
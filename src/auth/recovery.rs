@@ -0,0 +1,37 @@
+//! One-time recovery codes, a fallback for users who lost their second factor.
+
+use super::Error;
+use rand::Rng;
+
+pub const CODE_COUNT: usize = 10;
+// No 0/o, 1/l/i, so codes survive being copied by hand
+const ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// A fresh set of codes formatted as `xxxxx-xxxxx`, roughly 49 bits each.
+pub fn generate_codes() -> Vec<String> {
+    let mut rng = rand::thread_rng();
+    (0..CODE_COUNT)
+        .map(|_| {
+            let mut code: String = (0..10)
+                .map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())] as char)
+                .collect();
+            code.insert(5, '-');
+            code
+        })
+        .collect()
+}
+
+/// Argon2 hash to store, like for passwords, so the hashes of a leaked database cannot
+/// be brute forced quickly. Tolerates case and surrounding whitespace.
+pub fn hash_code(code: &str) -> Result<String, Error> {
+    super::hash_password(&normalize(code))
+}
+
+/// Checks `code` against a hash of [`hash_code`].
+pub fn verify_code(code: &str, hash: &str) -> Result<bool, Error> {
+    super::verify_password(&normalize(code), hash)
+}
+
+fn normalize(code: &str) -> String {
+    code.trim().to_lowercase()
+}
@@ -0,0 +1,258 @@
+//! Which roles include which, e.g. admins can do everything editors can. Checked for
+//! cycles when loaded, so [`RoleGraph::closure`] always terminates.
+
+use super::{Error, Permission, Role};
+use crate::config::RoleConfig;
+use std::collections::{HashMap, HashSet};
+
+/// A role `require_role` can check for, by its id. Besides [`Role`], applications can
+/// declare their own on a `#[repr(u8)]` enum with `#[derive(Clone, Copy, auth_middleware::Role)]`,
+/// and register it in a [`RoleRegistry`] to name its roles in the config and in forms.
+/// With [`super::SqliteStore`] every id needs a row in the `roles` table.
+pub trait AuthRole: Copy + Into<u8> + Send + Sync + 'static {
+    /// Every role of the type
+    const VARIANTS: &'static [Self];
+
+    fn name(self) -> &'static str;
+
+    /// The permission [`super::Backend`] grants to holders of the role, e.g. for the
+    /// generic `auth_role` middleware.
+    fn permission(self) -> Permission {
+        Permission::Role(self.into())
+    }
+}
+
+impl AuthRole for Role {
+    const VARIANTS: &'static [Self] = &Role::ALL;
+
+    fn name(self) -> &'static str {
+        Role::name(self)
+    }
+}
+
+/// The roles an application knows by name, [`Role`] and the [`AuthRole`] types
+/// registered with [`RoleRegistry::register`].
+#[derive(Debug, Clone)]
+pub struct RoleRegistry(HashMap<u8, &'static str>);
+
+impl Default for RoleRegistry {
+    fn default() -> Self {
+        Self(
+            Role::ALL
+                .into_iter()
+                .map(|role| (role.into(), role.name()))
+                .collect(),
+        )
+    }
+}
+
+impl RoleRegistry {
+    /// Adds the roles of `R`. Fails with [`Error::InvalidConfig`] if one of them reuses
+    /// the id or the name of a known role.
+    pub fn register<R: AuthRole>(mut self) -> Result<Self, Error> {
+        for &role in R::VARIANTS {
+            if self.0.contains_key(&role.into()) || self.by_name(role.name()).is_some() {
+                return Err(Error::InvalidConfig("two roles share an id or a name"));
+            }
+            self.0.insert(role.into(), role.name());
+        }
+        Ok(self)
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<u8> {
+        self.0
+            .iter()
+            .find(|(_, known)| **known == name)
+            .map(|(&role, _)| role)
+    }
+
+    pub fn name(&self, role: u8) -> Option<&'static str> {
+        self.0.get(&role).copied()
+    }
+
+    /// The ids and names of the known roles, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (u8, &'static str)> + '_ {
+        self.0.iter().map(|(&role, &name)| (role, name))
+    }
+
+    /// The names of the known roles among `roles`, ordered by id.
+    pub fn names<'a>(&self, roles: impl IntoIterator<Item = &'a u8>) -> Vec<String> {
+        let mut roles: Vec<u8> = roles.into_iter().copied().collect();
+        roles.sort_unstable();
+        roles
+            .into_iter()
+            .filter_map(|role| self.name(role))
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Like [`RoleRegistry::by_name`], for names from the config.
+    pub(super) fn config_role(&self, name: &str) -> Result<u8, Error> {
+        self.by_name(name)
+            .ok_or(Error::InvalidConfig("roles can only name known roles"))
+    }
+
+    fn display_name(&self, role: u8) -> String {
+        self.name(role)
+            .map_or_else(|| role.to_string(), str::to_string)
+    }
+}
+
+/// The roles each role inherits from.
+#[derive(Debug, Clone)]
+pub struct RoleGraph(HashMap<u8, Vec<u8>>);
+
+/// Admin inherits Editor, which inherits User, unless the config says otherwise.
+impl Default for RoleGraph {
+    fn default() -> Self {
+        Self(HashMap::from([
+            (Role::Editor.into(), vec![Role::User.into()]),
+            (Role::Admin.into(), vec![Role::Editor.into()]),
+        ]))
+    }
+}
+
+impl RoleGraph {
+    /// The default graph, with the parents of the roles setting `inherits` in `roles`
+    /// replaced. Names are looked up in `registry`. Fails with [`Error::RoleCycle`] if a
+    /// role ends up inheriting itself.
+    pub fn from_config(
+        roles: &HashMap<String, RoleConfig>,
+        registry: &RoleRegistry,
+    ) -> Result<Self, Error> {
+        let mut graph = Self::default();
+        for (name, config) in roles {
+            let Some(inherits) = &config.inherits else {
+                continue;
+            };
+            let role = registry.config_role(name)?;
+            let parents = inherits
+                .iter()
+                .map(|parent| registry.config_role(parent))
+                .collect::<Result<_, _>>()?;
+            graph.0.insert(role, parents);
+        }
+        graph.check_cycles(registry)?;
+        Ok(graph)
+    }
+
+    /// `roles` along with every role they inherit from, directly or not.
+    pub fn closure(&self, roles: impl IntoIterator<Item = u8>) -> HashSet<u8> {
+        let mut closure = HashSet::new();
+        let mut pending: Vec<u8> = roles.into_iter().collect();
+        while let Some(role) = pending.pop() {
+            if closure.insert(role) {
+                pending.extend(self.parents(role));
+            }
+        }
+        closure
+    }
+
+    fn parents(&self, role: u8) -> impl Iterator<Item = u8> + '_ {
+        self.0.get(&role).into_iter().flatten().copied()
+    }
+
+    fn check_cycles(&self, registry: &RoleRegistry) -> Result<(), Error> {
+        // Roles whose ancestors are known to be free of cycles
+        let mut done = HashSet::new();
+        for &role in self.0.keys() {
+            self.visit(role, &mut Vec::new(), &mut done, registry)?;
+        }
+        Ok(())
+    }
+
+    /// Depth-first walk up from `role`, `path` holds the roles leading to it.
+    fn visit(
+        &self,
+        role: u8,
+        path: &mut Vec<u8>,
+        done: &mut HashSet<u8>,
+        registry: &RoleRegistry,
+    ) -> Result<(), Error> {
+        if done.contains(&role) {
+            return Ok(());
+        }
+        if let Some(start) = path.iter().position(|&seen| seen == role) {
+            let cycle = path[start..]
+                .iter()
+                .chain([&role])
+                .map(|&role| registry.display_name(role))
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            return Err(Error::RoleCycle(cycle));
+        }
+
+        path.push(role);
+        for parent in self.parents(role) {
+            self.visit(parent, path, done, registry)?;
+        }
+        path.pop();
+        done.insert(role);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inherits(entries: &[(&str, &[&str])]) -> HashMap<String, RoleConfig> {
+        entries
+            .iter()
+            .map(|(name, parents)| {
+                let config = RoleConfig {
+                    inherits: Some(parents.iter().map(|parent| parent.to_string()).collect()),
+                    ..Default::default()
+                };
+                (name.to_string(), config)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn default_graph_inherits_down_to_user() {
+        let graph = RoleGraph::default();
+        assert_eq!(
+            graph.closure([Role::Admin.into()]),
+            HashSet::from([255, 150, 100])
+        );
+        assert_eq!(graph.closure([Role::User.into()]), HashSet::from([100]));
+    }
+
+    #[test]
+    fn config_replaces_parents() {
+        let registry = RoleRegistry::default();
+        let graph = RoleGraph::from_config(&inherits(&[("admin", &["user"])]), &registry).unwrap();
+        assert_eq!(graph.closure([255]), HashSet::from([255, 100]));
+        assert_eq!(graph.closure([150]), HashSet::from([150, 100]));
+    }
+
+    #[test]
+    fn rejects_cycles() {
+        let registry = RoleRegistry::default();
+        for config in [
+            inherits(&[("user", &["user"])]),
+            inherits(&[("user", &["admin"])]),
+            inherits(&[("user", &["editor"]), ("editor", &["user"])]),
+        ] {
+            match RoleGraph::from_config(&config, &registry) {
+                Err(Error::RoleCycle(cycle)) => assert!(cycle.contains("user"), "{cycle}"),
+                other => panic!("expected a cycle, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_roles() {
+        let registry = RoleRegistry::default();
+        for config in [
+            inherits(&[("owner", &["user"])]),
+            inherits(&[("admin", &["owner"])]),
+        ] {
+            assert!(matches!(
+                RoleGraph::from_config(&config, &registry),
+                Err(Error::InvalidConfig(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,773 @@
+use super::{
+    ApiKey, Error, ExternalIdentity, Group, LoginMethod, RoleRegistry, StoredPasskey, Token,
+    TokenKind, User, UserId, UserStore,
+};
+use axum::async_trait;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use std::str::FromStr;
+use time::OffsetDateTime;
+
+#[derive(Clone, Debug)]
+pub struct SqliteStore {
+    pool: SqlitePool,
+}
+
+impl SqliteStore {
+    /// Opens the database at `url` (e.g. `sqlite://auth.db` or `sqlite::memory:`),
+    /// creating it if needed, and runs the pending migrations.
+    pub async fn connect(url: &str) -> Result<Self, Error> {
+        let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+        // An in-memory database only lives as long as its connections, keep one around
+        let pool = SqlitePoolOptions::new()
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(options)
+            .await?;
+
+        let store = Self::new(pool);
+        store.migrate().await?;
+        Ok(store)
+    }
+
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    pub fn pool(&self) -> &SqlitePool {
+        &self.pool
+    }
+
+    pub async fn migrate(&self) -> Result<(), Error> {
+        sqlx::migrate!().run(&self.pool).await?;
+        Ok(())
+    }
+
+    /// Adds the roles of `registry` to the `roles` table, which the roles of users and
+    /// groups reference. The migrations only know the predefined roles, so call this
+    /// at startup once the app's own roles are registered.
+    pub async fn register_roles(&self, registry: &RoleRegistry) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+        for (id, name) in registry.iter() {
+            sqlx::query(
+                "INSERT INTO roles (id, name) VALUES (?, ?)
+                 ON CONFLICT (id) DO UPDATE SET name = excluded.name",
+            )
+            .bind(id)
+            .bind(name)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn user_from_row(&self, row: Option<UserRow>) -> Result<Option<User>, Error> {
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let roles = sqlx::query_scalar::<_, u8>("SELECT role_id FROM user_roles WHERE user_id = ?")
+            .bind(&row.id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let identities = sqlx::query_as::<_, (String, String, i64)>(
+            "SELECT provider, subject, created_at FROM user_identities WHERE user_id = ?",
+        )
+        .bind(&row.id)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|(provider, subject, created_at)| {
+            Ok(ExternalIdentity {
+                provider,
+                subject,
+                user_id: row.id.clone(),
+                created_at: OffsetDateTime::from_unix_timestamp(created_at)
+                    .map_err(|err| Error::Store(err.into()))?,
+            })
+        })
+        .collect::<Result<_, Error>>()?;
+
+        Ok(Some(User {
+            id: UserId(row.id),
+            username: row.username,
+            pw_hash: row.pw_hash,
+            roles,
+            verified: row.verified,
+            totp_secret: row.totp_secret,
+            identities,
+        }))
+    }
+
+    async fn groups_from_rows(&self, rows: Vec<(String, String)>) -> Result<Vec<Group>, Error> {
+        let mut groups = Vec::with_capacity(rows.len());
+        for (id, name) in rows {
+            let roles =
+                sqlx::query_scalar::<_, u8>("SELECT role_id FROM group_roles WHERE group_id = ?")
+                    .bind(&id)
+                    .fetch_all(&self.pool)
+                    .await?;
+            let permissions = sqlx::query_scalar(
+                "SELECT permission FROM group_permissions WHERE group_id = ? ORDER BY permission",
+            )
+            .bind(&id)
+            .fetch_all(&self.pool)
+            .await?;
+            let members =
+                sqlx::query_scalar("SELECT user_id FROM group_members WHERE group_id = ?")
+                    .bind(&id)
+                    .fetch_all(&self.pool)
+                    .await?;
+            groups.push(Group {
+                id,
+                name,
+                roles,
+                permissions,
+                members,
+            });
+        }
+        Ok(groups)
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct UserRow {
+    id: String,
+    username: String,
+    pw_hash: String,
+    verified: bool,
+    totp_secret: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct ApiKeyRow {
+    id: String,
+    user_id: String,
+    name: String,
+    hash: String,
+    scopes: Option<String>,
+    created_at: i64,
+    last_used_at: Option<i64>,
+    revoked_at: Option<i64>,
+}
+
+impl TryFrom<ApiKeyRow> for ApiKey {
+    type Error = Error;
+
+    fn try_from(row: ApiKeyRow) -> Result<Self, Error> {
+        Ok(ApiKey {
+            id: row.id,
+            user_id: row.user_id,
+            name: row.name,
+            hash: row.hash,
+            scopes: row
+                .scopes
+                .map(|scopes| serde_json::from_str(&scopes))
+                .transpose()
+                .map_err(|err| Error::Store(err.into()))?,
+            created_at: timestamp(row.created_at)?,
+            last_used_at: row.last_used_at.map(timestamp).transpose()?,
+            revoked_at: row.revoked_at.map(timestamp).transpose()?,
+        })
+    }
+}
+
+#[async_trait]
+impl UserStore for SqliteStore {
+    async fn get_user(&self, id: &str) -> Result<Option<User>, Error> {
+        let row = sqlx::query_as("SELECT * FROM users WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        self.user_from_row(row).await
+    }
+
+    async fn get_user_by_username(&self, username: &str) -> Result<Option<User>, Error> {
+        let row = sqlx::query_as("SELECT * FROM users WHERE username = ?")
+            .bind(username)
+            .fetch_optional(&self.pool)
+            .await?;
+        self.user_from_row(row).await
+    }
+
+    async fn get_user_by_identity(
+        &self,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Option<User>, Error> {
+        let row = sqlx::query_as(
+            "SELECT users.* FROM users
+             JOIN user_identities ON user_identities.user_id = users.id
+             WHERE user_identities.provider = ? AND user_identities.subject = ?",
+        )
+        .bind(provider)
+        .bind(subject)
+        .fetch_optional(&self.pool)
+        .await?;
+        self.user_from_row(row).await
+    }
+
+    async fn insert_user(&self, user: &User) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO users (id, username, pw_hash, verified, totp_secret)
+             VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&user.id.0)
+        .bind(&user.username)
+        .bind(&user.pw_hash)
+        .bind(user.verified)
+        .bind(&user.totp_secret)
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| match err {
+            sqlx::Error::Database(db_err) if db_err.is_unique_violation() => Error::UsernameTaken,
+            err => err.into(),
+        })?;
+        for role in &user.roles {
+            sqlx::query("INSERT INTO user_roles (user_id, role_id) VALUES (?, ?)")
+                .bind(&user.id.0)
+                .bind(role)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn update_password(&self, id: &str, pw_hash: &str) -> Result<(), Error> {
+        sqlx::query("UPDATE users SET pw_hash = ? WHERE id = ?")
+            .bind(pw_hash)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn set_verified(&self, id: &str, verified: bool) -> Result<(), Error> {
+        sqlx::query("UPDATE users SET verified = ? WHERE id = ?")
+            .bind(verified)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn set_totp_secret(&self, id: &str, secret: Option<&str>) -> Result<(), Error> {
+        sqlx::query("UPDATE users SET totp_secret = ?, totp_last_step = NULL WHERE id = ?")
+            .bind(secret)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn use_totp_step(&self, id: &str, step: i64) -> Result<bool, Error> {
+        let result = sqlx::query(
+            "UPDATE users SET totp_last_step = ?
+             WHERE id = ? AND (totp_last_step IS NULL OR totp_last_step < ?)",
+        )
+        .bind(step)
+        .bind(id)
+        .bind(step)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn add_second_factor_failure(&self, id: &str) -> Result<u32, Error> {
+        let failures: Option<u32> = sqlx::query_scalar(
+            "UPDATE users SET second_factor_failures = second_factor_failures + 1
+             WHERE id = ? RETURNING second_factor_failures",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(failures.unwrap_or_default())
+    }
+
+    async fn reset_second_factor_failures(
+        &self,
+        id: &str,
+        locked_until: Option<OffsetDateTime>,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            "UPDATE users SET second_factor_failures = 0, second_factor_locked_until = ?
+             WHERE id = ?",
+        )
+        .bind(locked_until.map(OffsetDateTime::unix_timestamp))
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_second_factor_lock(&self, id: &str) -> Result<Option<OffsetDateTime>, Error> {
+        let locked_until: Option<Option<i64>> =
+            sqlx::query_scalar("SELECT second_factor_locked_until FROM users WHERE id = ?")
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?;
+        locked_until
+            .flatten()
+            .map(OffsetDateTime::from_unix_timestamp)
+            .transpose()
+            .map_err(|err| Error::Store(err.into()))
+    }
+
+    async fn update_roles(&self, id: &str, roles: &[u8]) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM user_roles WHERE user_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        for role in roles {
+            sqlx::query("INSERT INTO user_roles (user_id, role_id) VALUES (?, ?)")
+                .bind(id)
+                .bind(role)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn delete_user(&self, id: &str) -> Result<(), Error> {
+        sqlx::query("DELETE FROM users WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn replace_recovery_codes(&self, user_id: &str, hashes: &[String]) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM recovery_codes WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        for hash in hashes {
+            sqlx::query("INSERT INTO recovery_codes (user_id, code_hash) VALUES (?, ?)")
+                .bind(user_id)
+                .bind(hash)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn get_recovery_codes(&self, user_id: &str) -> Result<Vec<String>, Error> {
+        Ok(
+            sqlx::query_scalar("SELECT code_hash FROM recovery_codes WHERE user_id = ?")
+                .bind(user_id)
+                .fetch_all(&self.pool)
+                .await?,
+        )
+    }
+
+    async fn take_recovery_code(&self, user_id: &str, hash: &str) -> Result<bool, Error> {
+        let res = sqlx::query("DELETE FROM recovery_codes WHERE user_id = ? AND code_hash = ?")
+            .bind(user_id)
+            .bind(hash)
+            .execute(&self.pool)
+            .await?;
+        Ok(res.rows_affected() == 1)
+    }
+
+    async fn insert_identity(&self, identity: &ExternalIdentity) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO user_identities (provider, subject, user_id, created_at)
+             VALUES (?, ?, ?, ?)",
+        )
+        .bind(&identity.provider)
+        .bind(&identity.subject)
+        .bind(&identity.user_id)
+        .bind(identity.created_at.unix_timestamp())
+        .execute(&self.pool)
+        .await
+        .map_err(|err| match err {
+            sqlx::Error::Database(db_err) if db_err.is_unique_violation() => Error::IdentityInUse,
+            err => err.into(),
+        })?;
+        Ok(())
+    }
+
+    async fn insert_passkey(&self, passkey: &StoredPasskey) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO passkeys (id, user_id, name, passkey, created_at) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&passkey.id)
+        .bind(&passkey.user_id)
+        .bind(&passkey.name)
+        .bind(passkey_json(passkey)?)
+        .bind(passkey.created_at.unix_timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_passkeys(&self, user_id: &str) -> Result<Vec<StoredPasskey>, Error> {
+        let rows: Vec<(String, String, String, i64)> = sqlx::query_as(
+            "SELECT id, name, passkey, created_at FROM passkeys WHERE user_id = ? ORDER BY created_at",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|(id, name, passkey, created_at)| {
+                Ok(StoredPasskey {
+                    id,
+                    user_id: user_id.into(),
+                    name,
+                    passkey: serde_json::from_str(&passkey)
+                        .map_err(|err| Error::Store(err.into()))?,
+                    created_at: OffsetDateTime::from_unix_timestamp(created_at)
+                        .map_err(|err| Error::Store(err.into()))?,
+                })
+            })
+            .collect()
+    }
+
+    async fn update_passkey(&self, passkey: &StoredPasskey) -> Result<(), Error> {
+        sqlx::query("UPDATE passkeys SET passkey = ? WHERE id = ?")
+            .bind(passkey_json(passkey)?)
+            .bind(&passkey.id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_login_method(
+        &self,
+        user_id: &str,
+        method: &LoginMethod,
+    ) -> Result<bool, Error> {
+        // Deleting first takes the write lock, so no other removal runs until the commit
+        let mut tx = self.pool.begin().await?;
+        let res = match method {
+            LoginMethod::Password => {
+                sqlx::query("UPDATE users SET pw_hash = '' WHERE id = ? AND pw_hash != ''")
+                    .bind(user_id)
+                    .execute(&mut *tx)
+                    .await?
+            }
+            LoginMethod::External { provider, subject } => sqlx::query(
+                "DELETE FROM user_identities WHERE user_id = ? AND provider = ? AND subject = ?",
+            )
+            .bind(user_id)
+            .bind(provider)
+            .bind(subject)
+            .execute(&mut *tx)
+            .await?,
+            LoginMethod::Passkey { id } => {
+                sqlx::query("DELETE FROM passkeys WHERE id = ? AND user_id = ?")
+                    .bind(id)
+                    .bind(user_id)
+                    .execute(&mut *tx)
+                    .await?
+            }
+        };
+        if res.rows_affected() != 1 {
+            return Ok(false);
+        }
+
+        let remaining: i64 = sqlx::query_scalar(
+            "SELECT (SELECT COUNT(*) FROM users WHERE id = ? AND pw_hash != '')
+                  + (SELECT COUNT(*) FROM user_identities WHERE user_id = ?)
+                  + (SELECT COUNT(*) FROM passkeys WHERE user_id = ?)",
+        )
+        .bind(user_id)
+        .bind(user_id)
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        // Dropping the transaction rolls the removal back
+        if remaining == 0 {
+            return Err(Error::LastLoginMethod);
+        }
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn insert_api_key(&self, api_key: &ApiKey) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO api_keys
+             (id, user_id, name, hash, scopes, created_at, last_used_at, revoked_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&api_key.id)
+        .bind(&api_key.user_id)
+        .bind(&api_key.name)
+        .bind(&api_key.hash)
+        .bind(
+            api_key
+                .scopes
+                .as_ref()
+                .map(serde_json::to_string)
+                .transpose()
+                .map_err(|err| Error::Store(err.into()))?,
+        )
+        .bind(api_key.created_at.unix_timestamp())
+        .bind(api_key.last_used_at.map(OffsetDateTime::unix_timestamp))
+        .bind(api_key.revoked_at.map(OffsetDateTime::unix_timestamp))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_api_keys(&self, user_id: &str) -> Result<Vec<ApiKey>, Error> {
+        sqlx::query_as::<_, ApiKeyRow>(
+            "SELECT * FROM api_keys WHERE user_id = ? ORDER BY created_at",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(ApiKey::try_from)
+        .collect()
+    }
+
+    async fn get_api_key_by_hash(&self, hash: &str) -> Result<Option<ApiKey>, Error> {
+        sqlx::query_as::<_, ApiKeyRow>("SELECT * FROM api_keys WHERE hash = ?")
+            .bind(hash)
+            .fetch_optional(&self.pool)
+            .await?
+            .map(ApiKey::try_from)
+            .transpose()
+    }
+
+    async fn touch_api_key(&self, id: &str, used_at: OffsetDateTime) -> Result<(), Error> {
+        sqlx::query("UPDATE api_keys SET last_used_at = ? WHERE id = ?")
+            .bind(used_at.unix_timestamp())
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn revoke_api_key(
+        &self,
+        user_id: &str,
+        id: &str,
+        revoked_at: OffsetDateTime,
+    ) -> Result<bool, Error> {
+        let res = sqlx::query(
+            "UPDATE api_keys SET revoked_at = ?
+             WHERE id = ? AND user_id = ? AND revoked_at IS NULL",
+        )
+        .bind(revoked_at.unix_timestamp())
+        .bind(id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected() == 1)
+    }
+
+    async fn insert_token(&self, token: &Token) -> Result<(), Error> {
+        sqlx::query("DELETE FROM tokens WHERE expires_at <= ?")
+            .bind(OffsetDateTime::now_utc().unix_timestamp())
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("INSERT INTO tokens (hash, kind, user_id, expires_at) VALUES (?, ?, ?, ?)")
+            .bind(&token.hash)
+            .bind(token.kind.as_str())
+            .bind(&token.user_id)
+            .bind(token.expires_at.unix_timestamp())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_token(&self, kind: TokenKind, hash: &str) -> Result<Option<Token>, Error> {
+        let row =
+            sqlx::query_as("SELECT user_id, expires_at FROM tokens WHERE hash = ? AND kind = ?")
+                .bind(hash)
+                .bind(kind.as_str())
+                .fetch_optional(&self.pool)
+                .await?;
+        token_from_row(kind, hash, row)
+    }
+
+    async fn take_token(&self, kind: TokenKind, hash: &str) -> Result<Option<Token>, Error> {
+        let row = sqlx::query_as(
+            "DELETE FROM tokens WHERE hash = ? AND kind = ? RETURNING user_id, expires_at",
+        )
+        .bind(hash)
+        .bind(kind.as_str())
+        .fetch_optional(&self.pool)
+        .await?;
+        token_from_row(kind, hash, row)
+    }
+
+    async fn delete_tokens(&self, user_id: &str, kind: TokenKind) -> Result<(), Error> {
+        sqlx::query("DELETE FROM tokens WHERE user_id = ? AND kind = ?")
+            .bind(user_id)
+            .bind(kind.as_str())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn insert_group(&self, group: &Group) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("INSERT INTO groups (id, name) VALUES (?, ?)")
+            .bind(&group.id)
+            .bind(&group.name)
+            .execute(&mut *tx)
+            .await
+            .map_err(|err| match err {
+                sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+                    Error::GroupNameTaken
+                }
+                err => err.into(),
+            })?;
+        for role in &group.roles {
+            sqlx::query("INSERT INTO group_roles (group_id, role_id) VALUES (?, ?)")
+                .bind(&group.id)
+                .bind(role)
+                .execute(&mut *tx)
+                .await?;
+        }
+        for permission in &group.permissions {
+            sqlx::query("INSERT INTO group_permissions (group_id, permission) VALUES (?, ?)")
+                .bind(&group.id)
+                .bind(permission)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn get_groups(&self) -> Result<Vec<Group>, Error> {
+        let rows = sqlx::query_as("SELECT id, name FROM groups ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?;
+        self.groups_from_rows(rows).await
+    }
+
+    async fn get_user_groups(&self, user_id: &str) -> Result<Vec<Group>, Error> {
+        let rows = sqlx::query_as(
+            "SELECT groups.id, groups.name FROM groups
+             JOIN group_members ON group_members.group_id = groups.id
+             WHERE group_members.user_id = ? ORDER BY groups.name",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        self.groups_from_rows(rows).await
+    }
+
+    async fn update_group_roles(&self, id: &str, roles: &[u8]) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM group_roles WHERE group_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        for role in roles {
+            sqlx::query("INSERT INTO group_roles (group_id, role_id) VALUES (?, ?)")
+                .bind(id)
+                .bind(role)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn update_group_permissions(
+        &self,
+        id: &str,
+        permissions: &[String],
+    ) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM group_permissions WHERE group_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        for permission in permissions {
+            sqlx::query("INSERT INTO group_permissions (group_id, permission) VALUES (?, ?)")
+                .bind(id)
+                .bind(permission)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn delete_group(&self, id: &str) -> Result<bool, Error> {
+        let res = sqlx::query("DELETE FROM groups WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(res.rows_affected() == 1)
+    }
+
+    async fn add_group_member(&self, group_id: &str, user_id: &str) -> Result<bool, Error> {
+        let mut tx = self.pool.begin().await?;
+        let exists = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM groups WHERE id = ?")
+            .bind(group_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        if exists == 0 {
+            return Err(Error::UnknownGroup);
+        }
+        let res =
+            sqlx::query("INSERT OR IGNORE INTO group_members (group_id, user_id) VALUES (?, ?)")
+                .bind(group_id)
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await?;
+        tx.commit().await?;
+
+        Ok(res.rows_affected() == 1)
+    }
+
+    async fn remove_group_member(&self, group_id: &str, user_id: &str) -> Result<bool, Error> {
+        let res = sqlx::query("DELETE FROM group_members WHERE group_id = ? AND user_id = ?")
+            .bind(group_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(res.rows_affected() == 1)
+    }
+}
+
+fn timestamp(unix: i64) -> Result<OffsetDateTime, Error> {
+    OffsetDateTime::from_unix_timestamp(unix).map_err(|err| Error::Store(err.into()))
+}
+
+fn passkey_json(passkey: &StoredPasskey) -> Result<String, Error> {
+    serde_json::to_string(&passkey.passkey).map_err(|err| Error::Store(err.into()))
+}
+
+fn token_from_row(
+    kind: TokenKind,
+    hash: &str,
+    row: Option<(String, i64)>,
+) -> Result<Option<Token>, Error> {
+    let Some((user_id, expires_at)) = row else {
+        return Ok(None);
+    };
+
+    Ok(Some(Token {
+        hash: hash.into(),
+        kind,
+        user_id,
+        expires_at: OffsetDateTime::from_unix_timestamp(expires_at)
+            .map_err(|err| Error::Store(err.into()))?,
+    }))
+}
@@ -0,0 +1,320 @@
+use super::{
+    ApiKey, Error, ExternalIdentity, Group, LoginMethod, StoredPasskey, Token, TokenKind, User,
+};
+use axum::async_trait;
+use std::sync::Arc;
+use time::OffsetDateTime;
+
+/// Persistence for [`User`]s, so [`super::Backend`] can sit on top of any database.
+#[async_trait]
+pub trait UserStore: Send + Sync + 'static {
+    async fn get_user(&self, id: &str) -> Result<Option<User>, Error>;
+
+    async fn get_user_by_username(&self, username: &str) -> Result<Option<User>, Error>;
+
+    async fn get_user_by_identity(
+        &self,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Option<User>, Error>;
+
+    /// Fails with [`Error::UsernameTaken`] if the username is already in use.
+    /// [`User::identities`] are not stored, they are linked with [`UserStore::insert_identity`].
+    async fn insert_user(&self, user: &User) -> Result<(), Error>;
+
+    async fn update_password(&self, id: &str, pw_hash: &str) -> Result<(), Error>;
+
+    async fn set_verified(&self, id: &str, verified: bool) -> Result<(), Error>;
+
+    /// Also forgets the step of [`UserStore::use_totp_step`].
+    async fn set_totp_secret(&self, id: &str, secret: Option<&str>) -> Result<(), Error>;
+
+    /// Records `step` as the last TOTP step `id` used, returns whether it is later than
+    /// the previous one. Must be atomic, so a code is only accepted once.
+    async fn use_totp_step(&self, id: &str, step: i64) -> Result<bool, Error>;
+
+    /// Counts a failed second factor attempt of `id`, returns the failures in a row.
+    async fn add_second_factor_failure(&self, id: &str) -> Result<u32, Error>;
+
+    /// Clears the failures of `id`, and locks the second factor until `locked_until`.
+    async fn reset_second_factor_failures(
+        &self,
+        id: &str,
+        locked_until: Option<OffsetDateTime>,
+    ) -> Result<(), Error>;
+
+    /// Until when the second factor of `id` is locked, even if that has passed.
+    async fn get_second_factor_lock(&self, id: &str) -> Result<Option<OffsetDateTime>, Error>;
+
+    async fn update_roles(&self, id: &str, roles: &[u8]) -> Result<(), Error>;
+
+    async fn delete_user(&self, id: &str) -> Result<(), Error>;
+
+    /// Replaces all recovery code hashes of a user, an empty slice removes them.
+    async fn replace_recovery_codes(&self, user_id: &str, hashes: &[String]) -> Result<(), Error>;
+
+    /// The recovery code hashes of a user.
+    async fn get_recovery_codes(&self, user_id: &str) -> Result<Vec<String>, Error>;
+
+    /// Removes a recovery code, returns whether it existed. Must be atomic, so a
+    /// code can only be used once.
+    async fn take_recovery_code(&self, user_id: &str, hash: &str) -> Result<bool, Error>;
+
+    /// Fails with [`Error::IdentityInUse`] if the identity is already linked to a user.
+    async fn insert_identity(&self, identity: &ExternalIdentity) -> Result<(), Error>;
+
+    async fn insert_passkey(&self, passkey: &StoredPasskey) -> Result<(), Error>;
+
+    async fn get_passkeys(&self, user_id: &str) -> Result<Vec<StoredPasskey>, Error>;
+
+    /// Saves the updated [`StoredPasskey::passkey`] after a login.
+    async fn update_passkey(&self, passkey: &StoredPasskey) -> Result<(), Error>;
+
+    /// Removes a way for `user_id` to log in, returns whether it existed. Fails with
+    /// [`Error::LastLoginMethod`] instead of removing the last one. Must be atomic, so
+    /// concurrent removals cannot leave the user without any.
+    async fn delete_login_method(&self, user_id: &str, method: &LoginMethod)
+        -> Result<bool, Error>;
+
+    async fn insert_api_key(&self, api_key: &ApiKey) -> Result<(), Error>;
+
+    /// Revoked keys included.
+    async fn get_api_keys(&self, user_id: &str) -> Result<Vec<ApiKey>, Error>;
+
+    async fn get_api_key_by_hash(&self, hash: &str) -> Result<Option<ApiKey>, Error>;
+
+    /// Records a use of the key.
+    async fn touch_api_key(&self, id: &str, used_at: OffsetDateTime) -> Result<(), Error>;
+
+    /// Revokes a key of `user_id`, returns whether it existed and was not revoked yet.
+    async fn revoke_api_key(
+        &self,
+        user_id: &str,
+        id: &str,
+        revoked_at: OffsetDateTime,
+    ) -> Result<bool, Error>;
+
+    async fn insert_token(&self, token: &Token) -> Result<(), Error>;
+
+    /// Looks a token up without consuming it, expired tokens included.
+    async fn get_token(&self, kind: TokenKind, hash: &str) -> Result<Option<Token>, Error>;
+
+    /// Removes the token and returns it. Must be atomic, so a token is only
+    /// ever returned once.
+    async fn take_token(&self, kind: TokenKind, hash: &str) -> Result<Option<Token>, Error>;
+
+    /// Removes every token of `kind` held by `user_id`.
+    async fn delete_tokens(&self, user_id: &str, kind: TokenKind) -> Result<(), Error>;
+
+    /// Fails with [`Error::GroupNameTaken`] if the name is already in use.
+    /// [`Group::members`] are not stored, they are added with [`UserStore::add_group_member`].
+    async fn insert_group(&self, group: &Group) -> Result<(), Error>;
+
+    async fn get_groups(&self) -> Result<Vec<Group>, Error>;
+
+    /// The groups `user_id` is a member of.
+    async fn get_user_groups(&self, user_id: &str) -> Result<Vec<Group>, Error>;
+
+    async fn update_group_roles(&self, id: &str, roles: &[u8]) -> Result<(), Error>;
+
+    async fn update_group_permissions(&self, id: &str, permissions: &[String])
+        -> Result<(), Error>;
+
+    /// Removes a group along with its memberships, returns whether it existed.
+    async fn delete_group(&self, id: &str) -> Result<bool, Error>;
+
+    /// Returns whether the user was not a member yet. Fails with
+    /// [`Error::UnknownGroup`] if there is no such group.
+    async fn add_group_member(&self, group_id: &str, user_id: &str) -> Result<bool, Error>;
+
+    /// Returns whether the user was a member.
+    async fn remove_group_member(&self, group_id: &str, user_id: &str) -> Result<bool, Error>;
+}
+
+/// A [`UserStore`] picked at startup, e.g. SQLite behind an optional htpasswd file. A
+/// named type instead of `dyn UserStore`, the compiler cannot prove that the futures of
+/// a `Backend<dyn UserStore>` are `Send`, which server functions and middlewares need.
+#[derive(Clone)]
+pub struct DynStore(Arc<dyn UserStore>);
+
+impl DynStore {
+    pub fn new(store: Arc<dyn UserStore>) -> Self {
+        Self(store)
+    }
+}
+
+#[async_trait]
+impl UserStore for DynStore {
+    async fn get_user(&self, id: &str) -> Result<Option<User>, Error> {
+        self.0.get_user(id).await
+    }
+
+    async fn get_user_by_username(&self, username: &str) -> Result<Option<User>, Error> {
+        self.0.get_user_by_username(username).await
+    }
+
+    async fn get_user_by_identity(
+        &self,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Option<User>, Error> {
+        self.0.get_user_by_identity(provider, subject).await
+    }
+
+    async fn insert_user(&self, user: &User) -> Result<(), Error> {
+        self.0.insert_user(user).await
+    }
+
+    async fn update_password(&self, id: &str, pw_hash: &str) -> Result<(), Error> {
+        self.0.update_password(id, pw_hash).await
+    }
+
+    async fn set_verified(&self, id: &str, verified: bool) -> Result<(), Error> {
+        self.0.set_verified(id, verified).await
+    }
+
+    async fn set_totp_secret(&self, id: &str, secret: Option<&str>) -> Result<(), Error> {
+        self.0.set_totp_secret(id, secret).await
+    }
+
+    async fn use_totp_step(&self, id: &str, step: i64) -> Result<bool, Error> {
+        self.0.use_totp_step(id, step).await
+    }
+
+    async fn add_second_factor_failure(&self, id: &str) -> Result<u32, Error> {
+        self.0.add_second_factor_failure(id).await
+    }
+
+    async fn reset_second_factor_failures(
+        &self,
+        id: &str,
+        locked_until: Option<OffsetDateTime>,
+    ) -> Result<(), Error> {
+        self.0.reset_second_factor_failures(id, locked_until).await
+    }
+
+    async fn get_second_factor_lock(&self, id: &str) -> Result<Option<OffsetDateTime>, Error> {
+        self.0.get_second_factor_lock(id).await
+    }
+
+    async fn update_roles(&self, id: &str, roles: &[u8]) -> Result<(), Error> {
+        self.0.update_roles(id, roles).await
+    }
+
+    async fn delete_user(&self, id: &str) -> Result<(), Error> {
+        self.0.delete_user(id).await
+    }
+
+    async fn replace_recovery_codes(&self, user_id: &str, hashes: &[String]) -> Result<(), Error> {
+        self.0.replace_recovery_codes(user_id, hashes).await
+    }
+
+    async fn get_recovery_codes(&self, user_id: &str) -> Result<Vec<String>, Error> {
+        self.0.get_recovery_codes(user_id).await
+    }
+
+    async fn take_recovery_code(&self, user_id: &str, hash: &str) -> Result<bool, Error> {
+        self.0.take_recovery_code(user_id, hash).await
+    }
+
+    async fn insert_identity(&self, identity: &ExternalIdentity) -> Result<(), Error> {
+        self.0.insert_identity(identity).await
+    }
+
+    async fn insert_passkey(&self, passkey: &StoredPasskey) -> Result<(), Error> {
+        self.0.insert_passkey(passkey).await
+    }
+
+    async fn get_passkeys(&self, user_id: &str) -> Result<Vec<StoredPasskey>, Error> {
+        self.0.get_passkeys(user_id).await
+    }
+
+    async fn update_passkey(&self, passkey: &StoredPasskey) -> Result<(), Error> {
+        self.0.update_passkey(passkey).await
+    }
+
+    async fn delete_login_method(
+        &self,
+        user_id: &str,
+        method: &LoginMethod,
+    ) -> Result<bool, Error> {
+        self.0.delete_login_method(user_id, method).await
+    }
+
+    async fn insert_api_key(&self, api_key: &ApiKey) -> Result<(), Error> {
+        self.0.insert_api_key(api_key).await
+    }
+
+    async fn get_api_keys(&self, user_id: &str) -> Result<Vec<ApiKey>, Error> {
+        self.0.get_api_keys(user_id).await
+    }
+
+    async fn get_api_key_by_hash(&self, hash: &str) -> Result<Option<ApiKey>, Error> {
+        self.0.get_api_key_by_hash(hash).await
+    }
+
+    async fn touch_api_key(&self, id: &str, used_at: OffsetDateTime) -> Result<(), Error> {
+        self.0.touch_api_key(id, used_at).await
+    }
+
+    async fn revoke_api_key(
+        &self,
+        user_id: &str,
+        id: &str,
+        revoked_at: OffsetDateTime,
+    ) -> Result<bool, Error> {
+        self.0.revoke_api_key(user_id, id, revoked_at).await
+    }
+
+    async fn insert_token(&self, token: &Token) -> Result<(), Error> {
+        self.0.insert_token(token).await
+    }
+
+    async fn get_token(&self, kind: TokenKind, hash: &str) -> Result<Option<Token>, Error> {
+        self.0.get_token(kind, hash).await
+    }
+
+    async fn take_token(&self, kind: TokenKind, hash: &str) -> Result<Option<Token>, Error> {
+        self.0.take_token(kind, hash).await
+    }
+
+    async fn delete_tokens(&self, user_id: &str, kind: TokenKind) -> Result<(), Error> {
+        self.0.delete_tokens(user_id, kind).await
+    }
+
+    async fn insert_group(&self, group: &Group) -> Result<(), Error> {
+        self.0.insert_group(group).await
+    }
+
+    async fn get_groups(&self) -> Result<Vec<Group>, Error> {
+        self.0.get_groups().await
+    }
+
+    async fn get_user_groups(&self, user_id: &str) -> Result<Vec<Group>, Error> {
+        self.0.get_user_groups(user_id).await
+    }
+
+    async fn update_group_roles(&self, id: &str, roles: &[u8]) -> Result<(), Error> {
+        self.0.update_group_roles(id, roles).await
+    }
+
+    async fn update_group_permissions(
+        &self,
+        id: &str,
+        permissions: &[String],
+    ) -> Result<(), Error> {
+        self.0.update_group_permissions(id, permissions).await
+    }
+
+    async fn delete_group(&self, id: &str) -> Result<bool, Error> {
+        self.0.delete_group(id).await
+    }
+
+    async fn add_group_member(&self, group_id: &str, user_id: &str) -> Result<bool, Error> {
+        self.0.add_group_member(group_id, user_id).await
+    }
+
+    async fn remove_group_member(&self, group_id: &str, user_id: &str) -> Result<bool, Error> {
+        self.0.remove_group_member(group_id, user_id).await
+    }
+}
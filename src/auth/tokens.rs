@@ -0,0 +1,55 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+
+/// What a single-use token can be redeemed for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    PasswordReset,
+    AccountVerification,
+    /// Renews a JWT access token, see [`super::jwt`]
+    Refresh,
+    /// Logs in without a password, from the browser session that asked for it
+    MagicLogin,
+}
+
+impl TokenKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenKind::PasswordReset => "password_reset",
+            TokenKind::AccountVerification => "account_verification",
+            TokenKind::Refresh => "refresh",
+            TokenKind::MagicLogin => "magic_login",
+        }
+    }
+}
+
+/// A stored single-use token. Only the hash of the token handed to the user is kept.
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub hash: String,
+    pub kind: TokenKind,
+    pub user_id: String,
+    pub expires_at: OffsetDateTime,
+}
+
+impl Token {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= OffsetDateTime::now_utc()
+    }
+}
+
+/// Returns a random URL safe token along with the hash to store.
+pub fn generate_token() -> (String, String) {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token = URL_SAFE_NO_PAD.encode(bytes);
+    let hash = hash_token(&token);
+    (token, hash)
+}
+
+/// Tokens are random, a fast unsalted hash is enough to keep them useless if the store leaks.
+pub fn hash_token(token: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes()))
+}
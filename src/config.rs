@@ -0,0 +1,329 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Server configuration, read from a TOML file with environment overrides.
+/// See `auth.example.toml` for the available settings.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub database_url: String,
+    /// Base URL used for links sent to users, e.g. password reset links
+    pub public_url: String,
+    pub session: SessionConfig,
+    pub outbox: OutboxConfig,
+    pub passkeys: PasskeyConfig,
+    /// OpenID Connect providers offered on the login page
+    pub oidc: Vec<OidcProviderConfig>,
+    pub jwt: JwtConfig,
+    pub proxy_auth: ProxyAuthConfig,
+    pub tls: TlsConfig,
+    pub htpasswd: HtpasswdConfig,
+    /// Replaces what a role grants and inherits, keyed by role name
+    pub roles: HashMap<String, RoleConfig>,
+    /// Registers the admin `leptos_user` with password `leptos_password` on start,
+    /// for development only
+    pub demo_admin: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            database_url: "sqlite://auth.db".into(),
+            public_url: "http://127.0.0.1:3000".into(),
+            session: SessionConfig::default(),
+            outbox: OutboxConfig::default(),
+            passkeys: PasskeyConfig::default(),
+            oidc: Vec::new(),
+            jwt: JwtConfig::default(),
+            proxy_auth: ProxyAuthConfig::default(),
+            tls: TlsConfig::default(),
+            htpasswd: HtpasswdConfig::default(),
+            roles: HashMap::new(),
+            demo_admin: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    pub store: SessionStoreKind,
+    /// Seconds between two sweeps of expired sessions
+    pub cleanup_interval_secs: u64,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            store: SessionStoreKind::default(),
+            cleanup_interval_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStoreKind {
+    Memory,
+    #[default]
+    Sqlite,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct OutboxConfig {
+    pub kind: OutboxKind,
+    /// Directory the file outbox writes to
+    pub dir: String,
+}
+
+impl Default for OutboxConfig {
+    fn default() -> Self {
+        Self {
+            kind: OutboxKind::default(),
+            dir: "outbox".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutboxKind {
+    #[default]
+    Console,
+    File,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PasskeyConfig {
+    pub enabled: bool,
+    /// Domain the passkeys are bound to, the host of `public_url` if unset
+    pub rp_id: Option<String>,
+    /// Name shown by the browser when creating a passkey
+    pub rp_name: String,
+}
+
+impl Default for PasskeyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            rp_id: None,
+            rp_name: "Leptos Auth".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OidcProviderConfig {
+    /// Identifies the provider in URLs, the callback is `/login/oidc/<name>/callback`
+    pub name: String,
+    /// Shown on the login button
+    pub display_name: String,
+    /// Endpoints and keys are discovered from `<issuer>/.well-known/openid-configuration`
+    pub issuer: String,
+    pub client_id: String,
+    /// Unset for public clients, which rely on PKCE alone
+    pub client_secret: Option<String>,
+    #[serde(default = "default_oidc_scopes")]
+    pub scopes: Vec<String>,
+}
+
+fn default_oidc_scopes() -> Vec<String> {
+    vec!["openid".into(), "profile".into(), "email".into()]
+}
+
+/// Stateless access tokens for API clients, see `auth::jwt`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct JwtConfig {
+    pub enabled: bool,
+    pub access_ttl_secs: u64,
+    pub refresh_ttl_secs: u64,
+    /// The first key signs new tokens, the others are only accepted. Rotate by
+    /// adding a key in front and dropping the old one once its tokens expired
+    pub keys: Vec<JwtKeyConfig>,
+}
+
+impl Default for JwtConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            access_ttl_secs: 15 * 60,
+            refresh_ttl_secs: 30 * 24 * 60 * 60,
+            keys: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct JwtKeyConfig {
+    /// Sent as the `kid` header, so tokens name the key that checks them
+    pub kid: String,
+    pub algorithm: JwtAlgorithm,
+    /// Shared secret for HS256
+    pub secret: Option<String>,
+    /// PEM Ed25519 private key for EdDSA, only needed for the signing key
+    pub private_key_file: Option<String>,
+    /// PEM Ed25519 public key for EdDSA
+    pub public_key_file: Option<String>,
+}
+
+impl std::fmt::Debug for JwtKeyConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JwtKeyConfig")
+            .field("kid", &self.kid)
+            .field("algorithm", &self.algorithm)
+            .field("secret", &self.secret.as_ref().map(|_| "[redacted]"))
+            .field("private_key_file", &self.private_key_file)
+            .field("public_key_file", &self.public_key_file)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum JwtAlgorithm {
+    #[serde(rename = "HS256")]
+    Hs256,
+    #[serde(rename = "EdDSA")]
+    EdDsa,
+}
+
+/// Logs in the user named by an authenticating reverse proxy, see `auth::proxy`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ProxyAuthConfig {
+    pub enabled: bool,
+    /// Request header holding the username
+    pub header: String,
+    /// CIDRs the proxy connects from, the header is ignored from anywhere else
+    pub trusted_proxies: Vec<String>,
+    /// Creates unknown users instead of refusing them
+    pub provision: bool,
+}
+
+impl Default for ProxyAuthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            header: "X-Forwarded-User".into(),
+            trusted_proxies: vec!["127.0.0.1/32".into(), "::1/128".into()],
+            provision: true,
+        }
+    }
+}
+
+/// Serving over HTTPS, optionally with client certificates, see `tls`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    pub enabled: bool,
+    /// PEM certificate chain of the server
+    pub cert_file: String,
+    /// PEM private key of the server
+    pub key_file: String,
+    /// PEM certificates of the CAs issuing client certificates. Clients are not asked
+    /// for a certificate if unset
+    pub client_ca_file: Option<String>,
+    /// Also accepts clients without a certificate, so they can log in another way
+    pub client_cert_optional: bool,
+    /// Which part of a client certificate is the username
+    pub username_from: CertUsernameSource,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cert_file: "tls/server.pem".into(),
+            key_file: "tls/server.key".into(),
+            client_ca_file: None,
+            client_cert_optional: true,
+            username_from: CertUsernameSource::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CertUsernameSource {
+    /// The `CN` of the subject
+    #[default]
+    CommonName,
+    /// The part before the `@` of the first email address in the SAN
+    SanEmail,
+    /// The first DNS name in the SAN, for machine clients
+    SanDns,
+}
+
+/// Users from an Apache-style htpasswd file, see `auth::htpasswd`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HtpasswdConfig {
+    pub enabled: bool,
+    /// `username:hash` lines with bcrypt or Argon2 hashes
+    pub file: String,
+    /// `username: role, role` lines, users not listed get the user role
+    pub roles_file: Option<String>,
+    /// How often the files are checked for changes
+    pub reload_interval_secs: u64,
+}
+
+impl Default for HtpasswdConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            file: ".htpasswd".into(),
+            roles_file: None,
+            reload_interval_secs: 5,
+        }
+    }
+}
+
+/// What a role grants, see `auth::permissions`, and inherits, see `auth::roles`.
+/// Unset fields keep the built-in value.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RoleConfig {
+    /// Permission names, e.g. `reports:read`
+    pub permissions: Option<Vec<String>>,
+    /// Names of the roles whose permissions this role gets too
+    pub inherits: Option<Vec<String>>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("failed to read config file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid config file: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("invalid value for {0}")]
+    InvalidValue(&'static str),
+}
+
+impl Config {
+    /// Loads the file named by `AUTH_CONFIG` (`auth.toml` by default) if it exists,
+    /// then applies the `DATABASE_URL` and `SESSION_STORE` environment variables.
+    pub fn load() -> Result<Self, ConfigError> {
+        let path = std::env::var("AUTH_CONFIG").unwrap_or_else(|_| "auth.toml".into());
+        let mut config = if Path::new(&path).exists() {
+            toml::from_str(&std::fs::read_to_string(&path)?)?
+        } else {
+            Self::default()
+        };
+
+        if let Ok(database_url) = std::env::var("DATABASE_URL") {
+            config.database_url = database_url;
+        }
+        if let Ok(store) = std::env::var("SESSION_STORE") {
+            config.session.store = match store.as_str() {
+                "memory" => SessionStoreKind::Memory,
+                "sqlite" => SessionStoreKind::Sqlite,
+                _ => return Err(ConfigError::InvalidValue("SESSION_STORE")),
+            };
+        }
+
+        Ok(config)
+    }
+}
@@ -0,0 +1,481 @@
+use crate::server_error_message;
+use leptos::*;
+use leptos_router::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupInfo {
+    pub id: String,
+    pub name: String,
+    /// Role names granted to the members
+    pub roles: Vec<String>,
+    /// Named permissions granted to the members, e.g. `reports:read`
+    pub permissions: Vec<String>,
+    pub members: Vec<GroupMember>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupMember {
+    pub id: String,
+    pub username: String,
+}
+
+#[component]
+pub fn GroupsPage() -> impl IntoView {
+    view! {
+        <Groups/>
+        <a href="/protected">"Back"</a>
+    }
+}
+
+#[island]
+fn Groups() -> impl IntoView {
+    let create_action = create_server_action::<CreateGroup>();
+    let delete_action = create_server_action::<DeleteGroup>();
+    let set_role_action = create_server_action::<SetGroupRole>();
+    let set_permissions_action = create_server_action::<SetGroupPermissions>();
+    let add_action = create_server_action::<AddGroupMember>();
+    let remove_action = create_server_action::<RemoveGroupMember>();
+    let groups = create_local_resource(
+        move || {
+            (
+                create_action.version().get(),
+                delete_action.version().get(),
+                set_role_action.version().get(),
+                set_permissions_action.version().get(),
+                add_action.version().get(),
+                remove_action.version().get(),
+            )
+        },
+        |_| list_groups(),
+    );
+    let error = move || {
+        create_action
+            .value()
+            .get()
+            .and_then(|res| res.err())
+            .or_else(|| delete_action.value().get().and_then(|res| res.err()))
+            .or_else(|| set_role_action.value().get().and_then(|res| res.err()))
+            .or_else(|| {
+                set_permissions_action
+                    .value()
+                    .get()
+                    .and_then(|res| res.err())
+            })
+            .or_else(|| add_action.value().get().and_then(|res| res.err()))
+            .or_else(|| remove_action.value().get().and_then(|res| res.err()))
+            .map(|err| server_error_message(&err))
+    };
+
+    view! {
+        <Suspense fallback=|| view! { <div uk-spinner></div> }>
+            {move || groups.get().map(|groups| match groups {
+                Ok(groups) if groups.is_empty() => view! {
+                    <p>"There are no groups yet."</p>
+                }.into_view(),
+                Ok(groups) => view! {
+                    <ul class="uk-list uk-list-divider">
+                        {groups.into_iter().map(|group| {
+                            let members = group.members.into_iter().map(|member| {
+                                let group_id = group.id.clone();
+                                view! {
+                                    <li>
+                                        {member.username}
+                                        <ActionForm action=remove_action>
+                                            <input type="hidden" name="group_id" value=group_id/>
+                                            <input type="hidden" name="user_id" value=member.id/>
+                                            <button class="uk-button uk-button-small" type="submit">
+                                                "Remove"
+                                            </button>
+                                        </ActionForm>
+                                    </li>
+                                }
+                            }).collect_view();
+                            // Each form keeps its own copy of the id
+                            let (add_id, role_id, permissions_id) =
+                                (group.id.clone(), group.id.clone(), group.id.clone());
+                            let permissions = group.permissions.join(", ");
+                            view! {
+                                <li>
+                                    <h4>{group.name} " (" {if group.roles.is_empty() {
+                                        "no roles".to_string()
+                                    } else {
+                                        group.roles.join(", ")
+                                    }} ")"</h4>
+                                    <p class="uk-text-meta">{if permissions.is_empty() {
+                                        "No other permissions".to_string()
+                                    } else {
+                                        format!("Also grants {permissions}")
+                                    }}</p>
+                                    <ul class="uk-list">{members}</ul>
+                                    <ActionForm action=add_action>
+                                        <input type="hidden" name="group_id" value=add_id/>
+                                        <input class="uk-input uk-form-width-medium" type="text"
+                                            name="username" placeholder="Username" required/>
+                                        <button class="uk-button uk-button-small" type="submit">
+                                            "Add member"
+                                        </button>
+                                    </ActionForm>
+                                    <ActionForm action=set_role_action>
+                                        <input type="hidden" name="id" value=role_id/>
+                                        <RoleSelect/>
+                                        <button class="uk-button uk-button-small" type="submit">
+                                            "Set role"
+                                        </button>
+                                    </ActionForm>
+                                    <ActionForm action=set_permissions_action>
+                                        <input type="hidden" name="id" value=permissions_id/>
+                                        <PermissionsInput value=permissions.clone()/>
+                                        <button class="uk-button uk-button-small" type="submit">
+                                            "Set permissions"
+                                        </button>
+                                    </ActionForm>
+                                    <ActionForm action=delete_action>
+                                        <input type="hidden" name="id" value=group.id/>
+                                        <button class="uk-button uk-button-small uk-button-danger" type="submit">
+                                            "Delete group"
+                                        </button>
+                                    </ActionForm>
+                                </li>
+                            }
+                        }).collect_view()}
+                    </ul>
+                }.into_view(),
+                Err(err) => server_error_message(&err).into_view(),
+            })}
+        </Suspense>
+        <ActionForm action=create_action>
+            <div class="uk-margin">
+                <input class="uk-input uk-form-width-medium" type="text" name="name"
+                    placeholder="Group name, e.g. Editors" maxlength="64" required/>
+            </div>
+            <div class="uk-margin">
+                <RoleSelect/>
+            </div>
+            <div class="uk-margin">
+                <PermissionsInput value=String::new()/>
+            </div>
+            <button class="button-auth" type="submit">"Create a group"</button>
+        </ActionForm>
+        <Show when=move || error().is_some()>
+            <p class="uk-text-danger">{error}</p>
+        </Show>
+    }
+}
+
+/// The `role` field of the group forms.
+#[component]
+fn RoleSelect() -> impl IntoView {
+    view! {
+        <select class="uk-select uk-form-width-medium" name="role">
+            <option value="none">"No role"</option>
+            <option value="user">"Member"</option>
+            <option value="editor">"Editor"</option>
+            <option value="admin">"Admin"</option>
+        </select>
+    }
+}
+
+/// The `permissions` field of the group forms, a comma separated list.
+#[component]
+fn PermissionsInput(value: String) -> impl IntoView {
+    view! {
+        <input class="uk-input uk-form-width-large" type="text" name="permissions" value=value
+            placeholder="Permissions, e.g. reports:read, data:read"/>
+    }
+}
+
+/// The permissions listed in a [`PermissionsInput`], failing with the first name that no
+/// role grants, so typos do not end up as permissions nothing checks.
+#[cfg(feature = "ssr")]
+fn parse_permissions(known: &[String], permissions: &str) -> Result<Vec<String>, String> {
+    let mut parsed = Vec::new();
+    for name in permissions
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        if !known.iter().any(|known| known == name) {
+            return Err(format!("Unknown permission {name}"));
+        }
+        if !parsed.iter().any(|parsed| parsed == name) {
+            parsed.push(name.to_string());
+        }
+    }
+    Ok(parsed)
+}
+
+/// The roles picked in a [`RoleSelect`], `None` for a name unknown to `registry`.
+#[cfg(feature = "ssr")]
+fn parse_role(registry: &crate::auth::RoleRegistry, role: &str) -> Option<Vec<u8>> {
+    match registry.by_name(role) {
+        Some(role) => Some(vec![role]),
+        None if role == "none" => Some(Vec::new()),
+        None => None,
+    }
+}
+
+/// Fails with 403 unless the caller has all of `roles` and `permissions`, so managing
+/// groups cannot hand out more than the manager has, e.g. make their own group admins.
+#[cfg(feature = "ssr")]
+async fn ensure_can_grant(roles: &[u8], permissions: &[String]) -> Result<(), ServerFnError> {
+    use crate::auth::{AuthSession, BearerAuth, Error};
+    use axum::Extension;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let bearer = leptos_axum::extract::<Option<Extension<BearerAuth>>>().await?;
+    let (user, scopes) = match &bearer {
+        Some(Extension(bearer)) => (Some(&bearer.user), bearer.scopes.as_ref()),
+        None => (auth_session.user.as_ref(), None),
+    };
+    let Some(user) = user else {
+        res.set_status(http::StatusCode::UNAUTHORIZED);
+        return Err(ServerFnError::new(""));
+    };
+
+    if !auth_session
+        .backend
+        .can_grant(user, scopes, roles, permissions)
+        .await?
+    {
+        res.set_status(http::StatusCode::FORBIDDEN);
+        return Err(ServerFnError::new(Error::RoleNotGranted));
+    }
+    Ok(())
+}
+
+/// The guard of every group server function, group managers only.
+#[cfg(feature = "ssr")]
+async fn require_group_manager(
+    req: http::Request<axum::body::Body>,
+) -> Result<http::Request<axum::body::Body>, http::Response<axum::body::Body>> {
+    let req = crate::require_app_login(req).await?;
+    crate::require_permission(req, crate::auth::permissions::GROUPS_MANAGE).await
+}
+
+/// The group `id`, failing with 404 if there is none.
+#[cfg(feature = "ssr")]
+async fn find_group(id: &str) -> Result<crate::auth::Group, ServerFnError> {
+    use crate::auth::{AuthSession, Error, UserStore};
+    use axum::Extension;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let groups = auth_session.backend.store().get_groups().await?;
+    match groups.into_iter().find(|group| group.id == id) {
+        Some(group) => Ok(group),
+        None => {
+            res.set_status(http::StatusCode::NOT_FOUND);
+            Err(ServerFnError::new(Error::UnknownGroup))
+        }
+    }
+}
+
+#[server(ListGroups)]
+#[middleware(crate::compose_from_fn!(require_group_manager))]
+async fn list_groups() -> Result<Vec<GroupInfo>, ServerFnError> {
+    use crate::auth::{AuthSession, UserStore};
+    use axum::Extension;
+
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let store = auth_session.backend.store();
+
+    let mut groups = Vec::new();
+    for group in store.get_groups().await? {
+        let mut members = Vec::new();
+        for user_id in group.members {
+            if let Some(user) = store.get_user(&user_id).await? {
+                members.push(GroupMember {
+                    id: user_id,
+                    username: user.username,
+                });
+            }
+        }
+        members.sort_by(|a, b| a.username.cmp(&b.username));
+        groups.push(GroupInfo {
+            id: group.id,
+            name: group.name,
+            roles: auth_session.backend.roles().names(&group.roles),
+            permissions: group.permissions,
+            members,
+        });
+    }
+    Ok(groups)
+}
+
+#[server(CreateGroup)]
+#[middleware(crate::compose_from_fn!(require_group_manager))]
+async fn create_group(
+    name: String,
+    role: String,
+    permissions: String,
+) -> Result<(), ServerFnError> {
+    use crate::auth::{AuthSession, Error};
+    use axum::Extension;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let Some(roles) = parse_role(auth_session.backend.roles(), &role) else {
+        res.set_status(http::StatusCode::BAD_REQUEST);
+        return Err(ServerFnError::new("Unknown role"));
+    };
+    let permissions =
+        match parse_permissions(&auth_session.backend.permission_names(), &permissions) {
+            Ok(permissions) => permissions,
+            Err(msg) => {
+                res.set_status(http::StatusCode::BAD_REQUEST);
+                return Err(ServerFnError::new(msg));
+            }
+        };
+    ensure_can_grant(&roles, &permissions).await?;
+
+    match auth_session
+        .backend
+        .create_group(&name, &roles, &permissions)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(err @ Error::InvalidGroupName(_)) => {
+            res.set_status(http::StatusCode::BAD_REQUEST);
+            Err(ServerFnError::new(err))
+        }
+        Err(err @ Error::GroupNameTaken) => {
+            res.set_status(http::StatusCode::CONFLICT);
+            Err(ServerFnError::new(err))
+        }
+        Err(_) => {
+            res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(ServerFnError::ServerError("".to_string()))
+        }
+    }
+}
+
+#[server(DeleteGroup)]
+#[middleware(crate::compose_from_fn!(require_group_manager))]
+async fn delete_group(id: String) -> Result<(), ServerFnError> {
+    use crate::auth::{AuthSession, UserStore};
+    use axum::Extension;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    // Deleting a group takes its roles and permissions away from the members
+    let group = find_group(&id).await?;
+    ensure_can_grant(&group.roles, &group.permissions).await?;
+
+    if !auth_session.backend.store().delete_group(&id).await? {
+        res.set_status(http::StatusCode::NOT_FOUND);
+        return Err(ServerFnError::new("No such group"));
+    }
+
+    Ok(())
+}
+
+#[server(SetGroupRole)]
+#[middleware(crate::compose_from_fn!(require_group_manager))]
+async fn set_group_role(id: String, role: String) -> Result<(), ServerFnError> {
+    use crate::auth::{AuthSession, UserStore};
+    use axum::Extension;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let Some(roles) = parse_role(auth_session.backend.roles(), &role) else {
+        res.set_status(http::StatusCode::BAD_REQUEST);
+        return Err(ServerFnError::new("Unknown role"));
+    };
+    // Both the roles taken away and the ones handed out
+    let current = find_group(&id).await?.roles;
+    ensure_can_grant(&[current, roles.clone()].concat(), &[]).await?;
+
+    auth_session
+        .backend
+        .store()
+        .update_group_roles(&id, &roles)
+        .await?;
+    Ok(())
+}
+
+#[server(SetGroupPermissions)]
+#[middleware(crate::compose_from_fn!(require_group_manager))]
+async fn set_group_permissions(id: String, permissions: String) -> Result<(), ServerFnError> {
+    use crate::auth::{AuthSession, UserStore};
+    use axum::Extension;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let permissions =
+        match parse_permissions(&auth_session.backend.permission_names(), &permissions) {
+            Ok(permissions) => permissions,
+            Err(msg) => {
+                res.set_status(http::StatusCode::BAD_REQUEST);
+                return Err(ServerFnError::new(msg));
+            }
+        };
+    // Both the permissions taken away and the ones handed out
+    let current = find_group(&id).await?.permissions;
+    ensure_can_grant(&[], &[current, permissions.clone()].concat()).await?;
+
+    auth_session
+        .backend
+        .store()
+        .update_group_permissions(&id, &permissions)
+        .await?;
+    Ok(())
+}
+
+#[server(AddGroupMember)]
+#[middleware(crate::compose_from_fn!(require_group_manager))]
+async fn add_group_member(group_id: String, username: String) -> Result<(), ServerFnError> {
+    use crate::auth::{AuthSession, Error, UserStore};
+    use axum::Extension;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let store = auth_session.backend.store();
+
+    let Some(user) = store.get_user_by_username(username.trim()).await? else {
+        res.set_status(http::StatusCode::NOT_FOUND);
+        return Err(ServerFnError::new("No such user"));
+    };
+    // Adding a member hands out the group's roles and permissions
+    let group = find_group(&group_id).await?;
+    ensure_can_grant(&group.roles, &group.permissions).await?;
+
+    match store.add_group_member(&group_id, &user.id.0).await {
+        Ok(true) => Ok(()),
+        Ok(false) => {
+            res.set_status(http::StatusCode::CONFLICT);
+            Err(ServerFnError::new("The user is already a member"))
+        }
+        Err(err @ Error::UnknownGroup) => {
+            res.set_status(http::StatusCode::NOT_FOUND);
+            Err(ServerFnError::new(err))
+        }
+        Err(_) => {
+            res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(ServerFnError::ServerError("".to_string()))
+        }
+    }
+}
+
+#[server(RemoveGroupMember)]
+#[middleware(crate::compose_from_fn!(require_group_manager))]
+async fn remove_group_member(group_id: String, user_id: String) -> Result<(), ServerFnError> {
+    use crate::auth::{AuthSession, UserStore};
+    use axum::Extension;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+
+    if !auth_session
+        .backend
+        .store()
+        .remove_group_member(&group_id, &user_id)
+        .await?
+    {
+        res.set_status(http::StatusCode::NOT_FOUND);
+        return Err(ServerFnError::new("The user is not a member"));
+    }
+
+    Ok(())
+}
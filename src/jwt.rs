@@ -0,0 +1,137 @@
+//! Endpoints for API clients using JWT sessions instead of the session cookie.
+//! They have fixed URLs under `/api/jwt/` and take form encoded arguments.
+
+use leptos::*;
+use serde::{Deserialize, Serialize};
+
+/// Shaped like an OAuth 2.0 token response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: String,
+    /// Seconds until the access token expires
+    pub expires_in: i64,
+    pub refresh_token: String,
+}
+
+#[cfg(feature = "ssr")]
+impl From<crate::auth::jwt::TokenPair> for TokenResponse {
+    fn from(pair: crate::auth::jwt::TokenPair) -> Self {
+        Self {
+            access_token: pair.access_token,
+            token_type: "Bearer".into(),
+            expires_in: pair.expires_in.whole_seconds(),
+            refresh_token: pair.refresh_token,
+        }
+    }
+}
+
+/// Logs in with a password, plus the current TOTP `code` or a recovery code for accounts
+/// with two-factor authentication, and returns a token pair.
+#[server(name = JwtLogin, prefix = "/api", endpoint = "jwt/login")]
+pub async fn jwt_login(
+    username: String,
+    password: String,
+    code: Option<String>,
+) -> Result<TokenResponse, ServerFnError> {
+    use crate::auth::{AuthSession, Credentials, Error};
+    use axum::Extension;
+    use axum_login::AuthnBackend;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    if !auth_session.backend.jwt_enabled() {
+        res.set_status(http::StatusCode::NOT_FOUND);
+        return Err(ServerFnError::new("JWT sessions are not enabled"));
+    }
+
+    let user = match auth_session
+        .backend
+        .authenticate(Credentials::Password { username, password })
+        .await
+    {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            res.set_status(http::StatusCode::UNAUTHORIZED);
+            return Err(ServerFnError::new("Invalid username or password"));
+        }
+        Err(_) => {
+            res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(ServerFnError::ServerError("".to_string()));
+        }
+    };
+    // No session to keep a pending login in, so the code comes with the password
+    if user.totp_secret.is_some() {
+        let Some(code) = code else {
+            res.set_status(http::StatusCode::UNAUTHORIZED);
+            return Err(ServerFnError::new("Invalid two-factor code"));
+        };
+        match auth_session
+            .backend
+            .verify_second_factor(&user, &code)
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => {
+                res.set_status(http::StatusCode::UNAUTHORIZED);
+                return Err(ServerFnError::new("Invalid two-factor code"));
+            }
+            Err(err @ Error::SecondFactorLocked) => {
+                res.set_status(http::StatusCode::TOO_MANY_REQUESTS);
+                return Err(ServerFnError::new(err));
+            }
+            Err(_) => {
+                res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+                return Err(ServerFnError::ServerError("".to_string()));
+            }
+        }
+    }
+
+    match auth_session.backend.issue_jwt(&user).await {
+        Ok(pair) => Ok(pair.into()),
+        Err(_) => {
+            res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(ServerFnError::ServerError("".to_string()))
+        }
+    }
+}
+
+/// Trades a refresh token for a new token pair, the old refresh token stops working.
+#[server(name = JwtRefresh, prefix = "/api", endpoint = "jwt/refresh")]
+pub async fn jwt_refresh(refresh_token: String) -> Result<TokenResponse, ServerFnError> {
+    use crate::auth::{AuthSession, Error};
+    use axum::Extension;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+
+    match auth_session.backend.refresh_jwt(&refresh_token).await {
+        Ok(pair) => Ok(pair.into()),
+        Err(err @ Error::InvalidToken) => {
+            res.set_status(http::StatusCode::UNAUTHORIZED);
+            Err(ServerFnError::new(err))
+        }
+        Err(err @ Error::JwtDisabled) => {
+            res.set_status(http::StatusCode::NOT_FOUND);
+            Err(ServerFnError::new(err))
+        }
+        Err(_) => {
+            res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(ServerFnError::ServerError("".to_string()))
+        }
+    }
+}
+
+/// Revokes a refresh token. The access tokens already issued expire on their own.
+#[server(name = JwtLogout, prefix = "/api", endpoint = "jwt/logout")]
+pub async fn jwt_logout(refresh_token: String) -> Result<(), ServerFnError> {
+    use crate::auth::AuthSession;
+    use axum::Extension;
+
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    auth_session
+        .backend
+        .revoke_refresh_token(&refresh_token)
+        .await?;
+    Ok(())
+}
@@ -1,14 +1,34 @@
+mod api_keys;
 pub mod error_template;
+mod groups;
+pub mod jwt;
+mod login_methods;
+mod magic_link;
+mod oidc;
+mod passkey;
+mod password_reset;
+mod register;
+mod two_factor;
+mod verification;
 
 #[cfg(feature = "ssr")]
 #[path = ""]
 mod ssr_modules {
     pub mod auth;
+    pub mod config;
     pub mod fileserv;
     pub mod middlewares;
+    pub mod outbox;
+    pub mod session_store;
+    pub mod tls;
 
-    pub(super) use middlewares::{auth_role, require_login};
+    pub(super) use middlewares::{
+        require_app_login, require_permission, require_session_login, require_verified,
+    };
 }
+/// Declares an application role enum, see [`auth::AuthRole`].
+#[cfg(feature = "ssr")]
+pub use auth_middleware_derive::Role;
 #[cfg(feature = "ssr")]
 pub use ssr_modules::*;
 
@@ -43,6 +63,18 @@ pub fn App() -> impl IntoView {
                 <Routes>
                     <Route path="" view=HomePage/>
                     <Route path="/protected" view=Authenticated/>
+                    <Route path="/register" view=register::RegisterPage/>
+                    <Route path="/forgot" view=password_reset::ForgotPasswordPage/>
+                    <Route path="/reset/:token" view=password_reset::ResetPasswordPage/>
+                    <Route path="/verify/:token" view=verification::VerifyAccountPage/>
+                    <Route path="/login/2fa" view=two_factor::SecondFactorPage/>
+                    <Route path="/login/magic/:token" view=magic_link::MagicLoginPage/>
+                    <Route path="/login/oidc/:provider/callback" view=oidc::OidcCallbackPage/>
+                    <Route path="/account/2fa" view=two_factor::TwoFactorSettingsPage/>
+                    <Route path="/account/passkeys" view=passkey::PasskeySettingsPage/>
+                    <Route path="/account/logins" view=login_methods::LoginMethodsPage/>
+                    <Route path="/account/api-keys" view=api_keys::ApiKeysPage/>
+                    <Route path="/admin/groups" view=groups::GroupsPage/>
                 </Routes>
             </main>
         </Router>
@@ -53,6 +85,11 @@ pub fn App() -> impl IntoView {
 fn HomePage() -> impl IntoView {
     view! {
         <Login/>
+        <oidc::OidcLogin/>
+        <magic_link::MagicLinkLogin/>
+        <a href="/register">"Create an account"</a>
+        <br/>
+        <a href="/forgot">"Forgot password?"</a>
     }
 }
 
@@ -64,12 +101,29 @@ fn Authenticated() -> impl IntoView {
             <ProtectedData/>
             <Logout/>
         </ProtectedOcean>
+        <a href="/account/2fa">"Two-factor authentication"</a>
+        <br/>
+        <a href="/account/passkeys">"Passkeys"</a>
+        <br/>
+        <a href="/account/logins">"Login methods"</a>
+        <br/>
+        <a href="/account/api-keys">"API keys"</a>
+        <br/>
+        <a href="/admin/groups">"Groups (admins only)"</a>
     }
 }
 
 #[derive(Clone)]
 struct LoggedIn(pub bool);
 
+/// The message of a server function error, without the variant prefix added by `Display`.
+fn server_error_message(err: &ServerFnError) -> String {
+    match err {
+        ServerFnError::ServerError(msg) => msg.clone(),
+        err => err.to_string(),
+    }
+}
+
 #[island]
 fn ProtectedOcean(children: Children) -> impl IntoView {
     let session_check = create_server_action::<CheckSession>();
@@ -104,7 +158,19 @@ fn ProtectedData() -> impl IntoView {
                 }>
                 <div class="uk-flex uk-flex-column uk-flex-middle">
                     <p class="sensitive-data">
-                        {data_fetched}
+                        {move || data_fetched.get().map(|res| match res {
+                            Ok(data) => data.into_view(),
+                            Err(err) => {
+                                let msg = server_error_message(&err);
+                                let not_verified = msg == verification::NOT_VERIFIED;
+                                view! {
+                                    {msg}
+                                    <Show when=move || not_verified>
+                                        <verification::ResendVerification/>
+                                    </Show>
+                                }.into_view()
+                            }
+                        })}
                     </p>
 
                     <Show when=move || super_secret_action.value().get().is_some_and(|v| v.is_ok())>
@@ -130,6 +196,25 @@ fn Login() -> impl IntoView {
     let login_action = create_server_action::<LoginSFn>();
     let session_check = create_server_action::<CheckSession>();
     let logged_in = create_local_resource(move || session_check.value().get(), |_| check_session());
+    let username_input = create_node_ref::<html::Input>();
+    let (passkey_error, set_passkey_error) = create_signal(None::<String>);
+
+    let on_passkey_login = move |_| {
+        let username = username_input
+            .get()
+            .map(|input| input.value())
+            .unwrap_or_default();
+        if username.is_empty() {
+            set_passkey_error(Some("Enter your username first".into()));
+            return;
+        }
+        spawn_local(async move {
+            match passkey::login_with_passkey(username).await {
+                Ok(()) => window().location().set_href("/protected").unwrap(),
+                Err(err) => set_passkey_error(Some(err)),
+            }
+        });
+    };
 
     view! {
         <Suspense fallback= move || view!{
@@ -146,10 +231,28 @@ fn Login() -> impl IntoView {
                 view!{
                     <>
                     <ActionForm action=login_action>
+                        <div class="uk-margin">
+                            <input class="uk-input uk-form-width-medium" type="text"
+                                name="username" placeholder="Username" autocomplete="username"
+                                node_ref=username_input required/>
+                        </div>
+                        <div class="uk-margin">
+                            <input class="uk-input uk-form-width-medium" type="password"
+                                name="password" placeholder="Password" autocomplete="current-password" required/>
+                        </div>
+                        <Show when=move || login_action.value().get().is_some_and(|v| v.is_err())>
+                            <p class="uk-text-danger">"Invalid username or password"</p>
+                        </Show>
                         <button class="button-auth uk-button-large" type="submit">
                             "Login"
                         </button>
                     </ActionForm>
+                    <Show when=move || passkey_error().is_some()>
+                        <p class="uk-text-danger">{passkey_error}</p>
+                    </Show>
+                    <button class="button-auth" type="button" on:click=on_passkey_login>
+                        "Login with a passkey"
+                    </button>
                     </>
                 }
             }}
@@ -173,21 +276,55 @@ fn Logout() -> impl IntoView {
     }
 }
 
+/// Logs `user` in, or holds off until the second factor if they enrolled one.
+/// Returns the page to continue on.
+#[cfg(feature = "ssr")]
+pub(crate) async fn complete_login(
+    auth_session: &mut auth::AuthSession,
+    user: &auth::User,
+) -> Result<&'static str, ServerFnError> {
+    use auth::{PendingSecondFactor, PENDING_SECOND_FACTOR_KEY};
+    use axum::Extension;
+    use axum_login::tower_sessions::Session;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+
+    // Hold off the login until the second factor is checked
+    if user.totp_secret.is_some() {
+        let Extension(session) = leptos_axum::extract::<Extension<Session>>().await?;
+        let pending = PendingSecondFactor {
+            user_id: user.id.0.clone(),
+        };
+        if session
+            .insert(PENDING_SECOND_FACTOR_KEY, pending)
+            .await
+            .is_err()
+        {
+            res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(ServerFnError::ServerError("".to_string()));
+        }
+        return Ok("/login/2fa");
+    }
+
+    if auth_session.login(user).await.is_err() {
+        res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+        return Err(ServerFnError::ServerError("".to_string()));
+    }
+    Ok("/protected")
+}
+
 #[server(LoginSFn)]
-async fn login() -> Result<(), ServerFnError> {
-    use auth::{AuthSession, UserId};
+async fn login(username: String, password: String) -> Result<(), ServerFnError> {
+    use auth::{AuthSession, Credentials};
     use axum::Extension;
 
     let res = expect_context::<leptos_axum::ResponseOptions>();
     let Extension(mut auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
     let user = match auth_session
-        .authenticate(UserId("leptos_user".into()))
+        .authenticate(Credentials::Password { username, password })
         .await
     {
-        Ok(Some(user)) => {
-            leptos_axum::redirect("/protected");
-            user
-        }
+        Ok(Some(user)) => user,
         Ok(None) => {
             res.set_status(http::StatusCode::UNAUTHORIZED);
             return Err(ServerFnError::new(""));
@@ -198,10 +335,8 @@ async fn login() -> Result<(), ServerFnError> {
         }
     };
 
-    if auth_session.login(&user).await.is_err() {
-        res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
-        return Err(ServerFnError::ServerError("".to_string()));
-    }
+    let next = complete_login(&mut auth_session, &user).await?;
+    leptos_axum::redirect(next);
 
     Ok(())
 }
@@ -224,13 +359,13 @@ async fn logout() -> Result<(), ServerFnError> {
 }
 
 #[server(CheckSession)]
-#[middleware(compose_from_fn!(require_login))]
+#[middleware(compose_from_fn!(require_app_login))]
 async fn check_session() -> Result<(), ServerFnError> {
     Ok(())
 }
 
 #[server(FetchData)]
-#[middleware(compose_from_fn!(require_login, |req| auth_role(req, auth::Role::User)))]
+#[middleware(compose_from_fn!(require_app_login, require_verified, |req| require_permission(req, auth::permissions::DATA_READ)))]
 async fn fetch_data() -> Result<String, ServerFnError> {
     use auth::AuthSession;
     use axum::Extension;
@@ -245,7 +380,7 @@ async fn fetch_data() -> Result<String, ServerFnError> {
 }
 
 #[server(FetchSecretData)]
-#[middleware(compose_from_fn!(require_login, |req| auth_role(req, auth::Role::Admin) ))]
+#[middleware(compose_from_fn!(require_app_login, |req| require_permission(req, auth::permissions::SECRET_READ)))]
 async fn super_secret_data() -> Result<String, ServerFnError> {
     use auth::UserId;
     use axum::Extension;
@@ -0,0 +1,291 @@
+use crate::oidc::{list_oidc_providers, StartOidcLink};
+use crate::server_error_message;
+use leptos::*;
+use leptos_router::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoginMethodInfo {
+    /// Names the method in [`UnlinkLoginMethod`]
+    pub key: String,
+    pub label: String,
+}
+
+#[component]
+pub fn LoginMethodsPage() -> impl IntoView {
+    view! {
+        <LoginMethods/>
+        <a href="/account/passkeys">"Passkeys"</a>
+        <br/>
+        <a href="/protected">"Back"</a>
+    }
+}
+
+#[island]
+fn LoginMethods() -> impl IntoView {
+    let unlink_action = create_server_action::<UnlinkLoginMethod>();
+    let password_action = create_server_action::<AddPassword>();
+    let link_action = create_server_action::<StartOidcLink>();
+    let methods = create_local_resource(
+        move || {
+            (
+                unlink_action.version().get(),
+                password_action.version().get(),
+            )
+        },
+        |_| list_login_methods(),
+    );
+    let providers = create_local_resource(|| (), |_| list_oidc_providers());
+    let error = move || {
+        unlink_action
+            .value()
+            .get()
+            .and_then(|res| res.err())
+            .or_else(|| password_action.value().get().and_then(|res| res.err()))
+            .or_else(|| link_action.value().get().and_then(|res| res.err()))
+            .map(|err| server_error_message(&err))
+    };
+
+    create_effect(move |_| {
+        if let Some(Ok(url)) = link_action.value().get() {
+            let _ = window().location().set_href(&url);
+        }
+    });
+
+    view! {
+        <Suspense fallback=|| view! { <div uk-spinner></div> }>
+            {move || methods.get().map(|methods| match methods {
+                Ok(methods) => {
+                    let has_password = methods.iter().any(|method| method.key == "password");
+                    view! {
+                        <p>"You can log in with:"</p>
+                        <ul class="uk-list">
+                            {methods.into_iter().map(|method| view! {
+                                <li>
+                                    {method.label}
+                                    <ActionForm action=unlink_action>
+                                        <input type="hidden" name="method" value=method.key/>
+                                        <button class="uk-button uk-button-small" type="submit">
+                                            "Remove"
+                                        </button>
+                                    </ActionForm>
+                                </li>
+                            }).collect_view()}
+                        </ul>
+                        <Show when=move || !has_password>
+                            <ActionForm action=password_action>
+                                <div class="uk-margin">
+                                    <input class="uk-input uk-form-width-medium" type="password"
+                                        name="password" placeholder="New password"
+                                        autocomplete="new-password" required/>
+                                </div>
+                                <div class="uk-margin">
+                                    <input class="uk-input uk-form-width-medium" type="password"
+                                        name="password_confirmation" placeholder="Confirm password"
+                                        autocomplete="new-password" required/>
+                                </div>
+                                <button class="button-auth" type="submit">"Add a password"</button>
+                            </ActionForm>
+                        </Show>
+                    }.into_view()
+                }
+                Err(err) => server_error_message(&err).into_view(),
+            })}
+            {move || providers.get().and_then(|res| res.ok()).map(|providers| {
+                providers.into_iter().map(|provider| view! {
+                    <ActionForm action=link_action>
+                        <input type="hidden" name="provider" value=provider.name/>
+                        <button class="button-auth" type="submit">
+                            "Link " {provider.display_name}
+                        </button>
+                    </ActionForm>
+                }).collect_view()
+            })}
+        </Suspense>
+        <Show when=move || error().is_some()>
+            <p class="uk-text-danger">{error}</p>
+        </Show>
+    }
+}
+
+#[cfg(feature = "ssr")]
+fn method_key(method: &crate::auth::LoginMethod) -> String {
+    use crate::auth::LoginMethod;
+
+    match method {
+        LoginMethod::Password => "password".into(),
+        LoginMethod::External { provider, subject } => format!("oidc:{provider}:{subject}"),
+        LoginMethod::Passkey { id } => format!("passkey:{id}"),
+    }
+}
+
+#[cfg(feature = "ssr")]
+fn parse_method_key(key: &str) -> Option<crate::auth::LoginMethod> {
+    use crate::auth::LoginMethod;
+
+    match key.split_once(':') {
+        None if key == "password" => Some(LoginMethod::Password),
+        Some(("oidc", identity)) => {
+            let (provider, subject) = identity.split_once(':')?;
+            Some(LoginMethod::External {
+                provider: provider.into(),
+                subject: subject.into(),
+            })
+        }
+        Some(("passkey", id)) => Some(LoginMethod::Passkey { id: id.into() }),
+        _ => None,
+    }
+}
+
+/// Logs the user in again after their password changed, which would end this session too.
+#[cfg(feature = "ssr")]
+async fn refresh_login(
+    auth_session: &mut crate::auth::AuthSession,
+    user_id: &str,
+) -> Result<(), ServerFnError> {
+    use crate::auth::UserStore;
+
+    if let Some(user) = auth_session.backend.store().get_user(user_id).await? {
+        auth_session.login(&user).await?;
+    }
+    Ok(())
+}
+
+#[server(ListLoginMethods)]
+#[middleware(crate::compose_from_fn!(crate::require_session_login))]
+async fn list_login_methods() -> Result<Vec<LoginMethodInfo>, ServerFnError> {
+    use crate::auth::oidc::OidcProviders;
+    use crate::auth::{AuthSession, LoginMethod, UserStore};
+    use axum::Extension;
+    use std::sync::Arc;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let Extension(providers) = leptos_axum::extract::<Extension<Arc<OidcProviders>>>().await?;
+    let Some(user) = auth_session.user else {
+        res.set_status(http::StatusCode::UNAUTHORIZED);
+        return Err(ServerFnError::new(""));
+    };
+
+    let methods = auth_session.backend.login_methods(&user).await?;
+    let passkeys = auth_session
+        .backend
+        .store()
+        .get_passkeys(&user.id.0)
+        .await?;
+    Ok(methods
+        .iter()
+        .map(|method| {
+            let label = match method {
+                LoginMethod::Password => "Password".into(),
+                LoginMethod::External { provider, .. } => providers
+                    .get(provider)
+                    .map_or(provider.as_str(), |provider| provider.display_name())
+                    .into(),
+                LoginMethod::Passkey { id } => passkeys
+                    .iter()
+                    .find(|passkey| &passkey.id == id)
+                    .map_or("Passkey".into(), |passkey| {
+                        format!("Passkey \"{}\"", passkey.name)
+                    }),
+            };
+            LoginMethodInfo {
+                key: method_key(method),
+                label,
+            }
+        })
+        .collect())
+}
+
+#[server(UnlinkLoginMethod)]
+#[middleware(crate::compose_from_fn!(crate::require_session_login))]
+async fn unlink_login_method(method: String) -> Result<(), ServerFnError> {
+    use crate::auth::{AuthSession, Error, LoginMethod};
+    use axum::Extension;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(mut auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let Some(user) = auth_session.user.clone() else {
+        res.set_status(http::StatusCode::UNAUTHORIZED);
+        return Err(ServerFnError::new(""));
+    };
+    let Some(method) = parse_method_key(&method) else {
+        res.set_status(http::StatusCode::BAD_REQUEST);
+        return Err(ServerFnError::new(Error::UnknownLoginMethod));
+    };
+
+    match auth_session
+        .backend
+        .unlink_login_method(&user, &method)
+        .await
+    {
+        Ok(()) => {}
+        Err(err @ Error::UnknownLoginMethod) => {
+            res.set_status(http::StatusCode::NOT_FOUND);
+            return Err(ServerFnError::new(err));
+        }
+        Err(err @ Error::LastLoginMethod) => {
+            res.set_status(http::StatusCode::BAD_REQUEST);
+            return Err(ServerFnError::new(err));
+        }
+        Err(err @ Error::UsersReadOnly) => {
+            res.set_status(http::StatusCode::FORBIDDEN);
+            return Err(ServerFnError::new(err));
+        }
+        Err(_) => {
+            res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(ServerFnError::ServerError("".to_string()));
+        }
+    }
+
+    if method == LoginMethod::Password {
+        refresh_login(&mut auth_session, &user.id.0).await?;
+    }
+
+    Ok(())
+}
+
+#[server(AddPassword)]
+#[middleware(crate::compose_from_fn!(crate::require_session_login))]
+async fn add_password(
+    password: String,
+    password_confirmation: String,
+) -> Result<(), ServerFnError> {
+    use crate::auth::{AuthSession, Error};
+    use axum::Extension;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(mut auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let Some(user) = auth_session.user.clone() else {
+        res.set_status(http::StatusCode::UNAUTHORIZED);
+        return Err(ServerFnError::new(""));
+    };
+    // Changing an existing password goes through the reset flow, which proves ownership
+    if user.has_password() {
+        res.set_status(http::StatusCode::BAD_REQUEST);
+        return Err(ServerFnError::new("The account already has a password"));
+    }
+    if password != password_confirmation {
+        res.set_status(http::StatusCode::BAD_REQUEST);
+        return Err(ServerFnError::new("Passwords do not match"));
+    }
+
+    match auth_session.backend.set_password(&user, &password).await {
+        Ok(()) => {}
+        Err(err @ Error::WeakPassword(_)) => {
+            res.set_status(http::StatusCode::BAD_REQUEST);
+            return Err(ServerFnError::new(err));
+        }
+        Err(err @ Error::UsersReadOnly) => {
+            res.set_status(http::StatusCode::FORBIDDEN);
+            return Err(ServerFnError::new(err));
+        }
+        Err(_) => {
+            res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(ServerFnError::ServerError("".to_string()));
+        }
+    }
+    refresh_login(&mut auth_session, &user.id.0).await?;
+
+    Ok(())
+}
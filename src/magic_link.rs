@@ -0,0 +1,184 @@
+use crate::server_error_message;
+use leptos::*;
+use leptos_router::*;
+
+/// Session key of the hash of the last magic link sent from this browser. Links only
+/// log in the browser that asked for them.
+#[cfg(feature = "ssr")]
+const PENDING_MAGIC_LOGIN_KEY: &str = "auth.pending_magic_login";
+
+/// Asks for a login link instead of a password.
+#[island]
+pub fn MagicLinkLogin() -> impl IntoView {
+    let request_action = create_server_action::<RequestMagicLink>();
+
+    view! {
+        <Show
+            when=move || request_action.value().get().is_some_and(|v| v.is_ok())
+            fallback=move || view! {
+                <ActionForm action=request_action>
+                    <div class="uk-margin">
+                        <input class="uk-input uk-form-width-medium" type="text"
+                            name="username" placeholder="Username" autocomplete="username" required/>
+                    </div>
+                    <button class="button-auth" type="submit">
+                        "Send me a login link"
+                    </button>
+                </ActionForm>
+            }
+        >
+            <p>"If the account exists, a login link is on its way. Open it in this browser."</p>
+        </Show>
+    }
+}
+
+#[component]
+pub fn MagicLoginPage() -> impl IntoView {
+    let params = use_params_map();
+    let token = params.with_untracked(|params| params.get("token").cloned().unwrap_or_default());
+
+    view! {
+        <MagicLogin token/>
+    }
+}
+
+#[island]
+fn MagicLogin(token: String) -> impl IntoView {
+    let finish_action = create_server_action::<FinishMagicLogin>();
+    let error = move || {
+        finish_action
+            .value()
+            .get()
+            .and_then(|res| res.err())
+            .map(|err| server_error_message(&err))
+    };
+
+    create_effect(move |_| {
+        finish_action.dispatch(FinishMagicLogin {
+            token: token.clone(),
+        });
+    });
+    create_effect(move |_| {
+        if let Some(Ok(next)) = finish_action.value().get() {
+            let _ = window().location().set_href(&next);
+        }
+    });
+
+    view! {
+        <Show
+            when=move || error().is_some()
+            fallback=|| view! { <div uk-spinner></div> }
+        >
+            <p class="uk-text-danger">{error}</p>
+            <a href="/">"Back to login"</a>
+        </Show>
+    }
+}
+
+/// Mails `username` a link to log in with. The link carries a random single-use token
+/// whose hash is stored, like password reset links, rather than a signature over the
+/// user and expiry: a signed link could not be used up, and would stay valid after the
+/// password changes. A stored token is deleted on use and by [`set_password`].
+///
+/// [`set_password`]: crate::auth::Backend::set_password
+#[server(RequestMagicLink)]
+async fn request_magic_link(username: String) -> Result<(), ServerFnError> {
+    use crate::auth::{hash_token, AuthSession, TokenKind, UserStore};
+    use crate::config::Config;
+    use crate::outbox::{Message, SharedOutbox};
+    use axum::Extension;
+    use axum_login::tower_sessions::Session;
+    use std::sync::Arc;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let Extension(session) = leptos_axum::extract::<Extension<Session>>().await?;
+    let Extension(config) = leptos_axum::extract::<Extension<Arc<Config>>>().await?;
+    let Extension(outbox) = leptos_axum::extract::<Extension<SharedOutbox>>().await?;
+
+    // Unknown usernames get the same answer, so this can't be used to probe for accounts
+    let user = match auth_session
+        .backend
+        .store()
+        .get_user_by_username(&username)
+        .await
+    {
+        Ok(Some(user)) => user,
+        Ok(None) => return Ok(()),
+        Err(_) => {
+            res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(ServerFnError::ServerError("".to_string()));
+        }
+    };
+
+    let Ok(token) = auth_session
+        .backend
+        .issue_token(
+            &user.id.0,
+            TokenKind::MagicLogin,
+            time::Duration::minutes(15),
+        )
+        .await
+    else {
+        res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+        return Err(ServerFnError::ServerError("".to_string()));
+    };
+    session
+        .insert(PENDING_MAGIC_LOGIN_KEY, hash_token(&token))
+        .await?;
+
+    let message = Message {
+        to: user.username,
+        subject: "Your login link".into(),
+        body: format!(
+            "Open this link within 15 minutes, in the browser you asked for it from, to log in:\n{}/login/magic/{token}",
+            config.public_url.trim_end_matches('/')
+        ),
+    };
+    if let Err(err) = outbox.send(message).await {
+        res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+        return Err(ServerFnError::ServerError(err.to_string()));
+    }
+
+    Ok(())
+}
+
+#[server(FinishMagicLogin)]
+async fn finish_magic_login(token: String) -> Result<String, ServerFnError> {
+    use crate::auth::{hash_token, AuthSession, Error, TokenKind};
+    use axum::Extension;
+    use axum_login::tower_sessions::Session;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(mut auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let Extension(session) = leptos_axum::extract::<Extension<Session>>().await?;
+
+    // A link opened elsewhere, e.g. by a mail scanner, must not use up the token
+    let pending = session.get::<String>(PENDING_MAGIC_LOGIN_KEY).await?;
+    if pending.as_deref() != Some(hash_token(&token).as_str()) {
+        res.set_status(http::StatusCode::BAD_REQUEST);
+        return Err(ServerFnError::new(
+            "Open the link in the browser you asked for it from",
+        ));
+    }
+    session.remove::<String>(PENDING_MAGIC_LOGIN_KEY).await?;
+
+    let user = match auth_session
+        .backend
+        .redeem_token(TokenKind::MagicLogin, &token)
+        .await
+    {
+        Ok(user) => user,
+        Err(err @ Error::InvalidToken) => {
+            res.set_status(http::StatusCode::BAD_REQUEST);
+            return Err(ServerFnError::new(err));
+        }
+        Err(_) => {
+            res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(ServerFnError::ServerError("".to_string()));
+        }
+    };
+
+    let next = crate::complete_login(&mut auth_session, &user).await?;
+    Ok(next.into())
+}
@@ -2,7 +2,7 @@
 #[tokio::main]
 async fn main() {
     use auth_middleware::fileserv::file_and_error_handler;
-    use axum::Router;
+    use axum::{Extension, Router};
     use leptos::*;
     use leptos_axum::{generate_route_list, LeptosRoutes};
 
@@ -16,31 +16,140 @@ async fn main() {
     let addr = leptos_options.site_addr;
     let routes = generate_route_list(auth_middleware::App);
 
-    use auth_middleware::auth;
-    use axum_login::tower_sessions::{MemoryStore, SessionManagerLayer};
+    use auth_middleware::auth::{self, UserStore};
+    use auth_middleware::config::Config;
+    use auth_middleware::outbox;
+    use auth_middleware::session_store::AppSessionStore;
+    use axum_login::tower_sessions::SessionManagerLayer;
     use axum_login::AuthManagerLayerBuilder;
 
-    let mut auth_backend = auth::Backend::default();
-    // roles: Admin = 255 and User = 100
-    let _ = auth_backend.register_user("leptos_user", &[255]);
+    let config = Config::load().unwrap();
+    // Register the application's own `AuthRole` types here to name them in the config
+    let role_registry = auth::RoleRegistry::default();
 
-    let auth_layer = AuthManagerLayerBuilder::new(
-        auth_backend,
-        SessionManagerLayer::new(MemoryStore::default()),
-    )
-    .build();
+    #[cfg(not(feature = "memory-store"))]
+    let user_store = std::sync::Arc::new(
+        auth::SqliteStore::connect(&config.database_url)
+            .await
+            .unwrap(),
+    );
+    #[cfg(not(feature = "memory-store"))]
+    user_store.register_roles(&role_registry).await.unwrap();
+    // The session store shares the connections of the user store
+    #[cfg(not(feature = "memory-store"))]
+    let sqlite_pool = Some(user_store.pool().clone());
+    #[cfg(feature = "memory-store")]
+    let user_store = std::sync::Arc::new(auth::MemoryStore::default());
+    #[cfg(feature = "memory-store")]
+    let sqlite_pool = None;
+
+    // The file decides who the users are, the store keeps everything else about them
+    let user_store: std::sync::Arc<dyn UserStore> = if config.htpasswd.enabled {
+        let htpasswd_store = std::sync::Arc::new(
+            auth::HtpasswdStore::open(&config.htpasswd, user_store, role_registry.clone())
+                .await
+                .unwrap(),
+        );
+        htpasswd_store.spawn_reload(std::time::Duration::from_secs(
+            config.htpasswd.reload_interval_secs,
+        ));
+        htpasswd_store
+    } else {
+        user_store
+    };
+
+    let mut auth_backend =
+        auth::AppBackend::new(std::sync::Arc::new(auth::DynStore::new(user_store)))
+            .with_role_permissions(
+                auth::RolePermissions::from_config(&config.roles, &role_registry).unwrap(),
+            )
+            .with_role_graph(auth::RoleGraph::from_config(&config.roles, &role_registry).unwrap())
+            .with_roles(role_registry.clone());
+    if config.passkeys.enabled {
+        auth_backend = auth_backend.with_webauthn(
+            auth::passkey::webauthn(
+                &config.public_url,
+                config.passkeys.rp_id.as_deref(),
+                &config.passkeys.rp_name,
+            )
+            .unwrap(),
+        );
+    }
+    if config.jwt.enabled {
+        auth_backend = auth_backend
+            .with_jwt(auth::jwt::JwtKeys::from_config(&config.jwt, &config.public_url).unwrap());
+    }
+    // roles: Admin = 255, Editor = 150 and User = 100. Fails with htpasswd users, they come from the file
+    if config.demo_admin {
+        if let Ok(user) = auth_backend
+            .register_user("leptos_user", "leptos_password", &[255])
+            .await
+        {
+            let _ = auth_backend.store().set_verified(&user.id.0, true).await;
+        }
+    }
+
+    let session_store =
+        AppSessionStore::from_config(&config.session, sqlite_pool.as_ref(), &config.database_url)
+            .await
+            .unwrap();
+    session_store.spawn_expired_deletion(std::time::Duration::from_secs(
+        config.session.cleanup_interval_secs,
+    ));
+
+    let auth_layer =
+        AuthManagerLayerBuilder::new(auth_backend, SessionManagerLayer::new(session_store)).build();
+
+    let outbox = outbox::from_config(&config.outbox);
+    let oidc_providers = std::sync::Arc::new(auth::oidc::OidcProviders::from_config(
+        &config.oidc,
+        &config.public_url,
+    ));
+
+    let tls_config = config
+        .tls
+        .enabled
+        .then(|| auth_middleware::tls::server_config(&config.tls).unwrap());
+    let proxy_auth = config.proxy_auth.enabled.then(|| {
+        std::sync::Arc::new(auth::proxy::ProxyAuth::from_config(&config.proxy_auth).unwrap())
+    });
 
     // build our application with a route
-    let app = Router::new()
-        .leptos_routes(&leptos_options, routes, auth_middleware::App)
+    let mut app = Router::new().leptos_routes(&leptos_options, routes, auth_middleware::App);
+    if let Some(proxy_auth) = proxy_auth {
+        // Inside the auth layer, which provides the session to log into
+        app = app.layer(axum::middleware::from_fn(
+            move |req: axum::extract::Request, next: axum::middleware::Next| {
+                let proxy_auth = std::sync::Arc::clone(&proxy_auth);
+                async move {
+                    match auth_middleware::middlewares::proxy_auth(req, proxy_auth).await {
+                        Ok(req) => next.run(req).await,
+                        Err(res) => res,
+                    }
+                }
+            },
+        ));
+    }
+    let app = app
         .layer(auth_layer)
+        .layer(Extension(outbox))
+        .layer(Extension(oidc_providers))
+        .layer(Extension(std::sync::Arc::new(config)))
         .fallback(file_and_error_handler)
         .with_state(leptos_options);
 
     let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    axum::serve(listener, app.into_make_service())
-        .await
-        .unwrap();
+    if let Some(tls_config) = tls_config {
+        auth_middleware::tls::serve(listener, app, tls_config).await;
+        return;
+    }
+    // The peer address tells trusted proxies apart
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
 
 #[cfg(not(feature = "ssr"))]
@@ -3,86 +3,481 @@ mod macros;
 pub use macros::MiddlewareLayer;
 
 use super::auth;
+use super::config::Config;
+use super::tls::ClientCertificate;
 
 use axum::body::Body;
-use axum_login::{AuthUser, AuthzBackend};
+use axum::extract::ConnectInfo;
+use axum_login::tower_sessions::Session;
+use axum_login::{AuthUser, AuthnBackend, AuthzBackend};
 use http::{Request, Response, StatusCode};
+use leptos::server_fn::error::ServerFnErrorSerde;
+use leptos::ServerFnError;
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::sync::Arc;
 
-pub async fn require_login(req: Request<Body>) -> Result<Request<Body>, Response<Body>> {
+/// Lets the request through if a user is logged in, for any axum-login backend `B`, e.g.
+/// `compose_from_fn!(require_login::<MyBackend>)`. Requests an earlier middleware
+/// authenticated without the session, by inserting [`auth::BearerAuth`] with the user and
+/// permission types of `B`, count as logged in too. Others are sent to `/`.
+pub async fn require_login<B>(req: Request<Body>) -> Result<Request<Body>, Response<Body>>
+where
+    B: AuthnBackend + AuthzBackend + 'static,
+{
+    if req
+        .extensions()
+        .get::<auth::BearerAuth<B::User, B::Permission>>()
+        .is_some()
+    {
+        return Ok(req);
+    }
+
+    let Some(auth_session) = req.extensions().get::<axum_login::AuthSession<B>>() else {
+        return Err(internal_error());
+    };
+    if auth_session.user.is_none() {
+        return Err(redirect_to_login(&req, "/"));
+    }
+
+    Ok(req)
+}
+
+/// Lets the request through if a user is logged in to the session of [`auth::Backend`].
+/// A user who passed the password check but not the second factor yet is still logged
+/// out, and is sent back to the second factor page.
+pub async fn require_session_login(req: Request<Body>) -> Result<Request<Body>, Response<Body>> {
     let Some(auth_session) = req.extensions().get::<auth::AuthSession>() else {
-        return Err(Response::builder()
-            .status(StatusCode::INTERNAL_SERVER_ERROR)
-            .body(Body::empty())
-            .unwrap());
+        return Err(internal_error());
     };
+    if auth_session.user.is_some() {
+        return Ok(req);
+    }
 
-    if auth_session.user.is_none() {
-        let headers = req.headers();
-        let base_url: Option<&str> =
-            headers
-                .get(http::header::REFERER)
-                .and_then(|referer| match referer.to_str() {
-                    Ok(referer_str) => {
-                        headers
-                            .get(http::header::ORIGIN)
-                            .and_then(|origin| match origin.to_str() {
-                                Ok(origin_str) => referer_str.strip_prefix(origin_str),
-                                Err(_) => None,
-                            })
-                    }
-                    Err(_) => None,
-                });
-
-        if let Some(base_url) = base_url {
-            // Redirect if the request did not originated from the login page
-            if base_url != "/" {
-                leptos_axum::redirect("/");
-            }
+    let session = req.extensions().get::<Session>().cloned();
+    let login_page = if second_factor_pending(session).await {
+        "/login/2fa"
+    } else {
+        "/"
+    };
+    Err(redirect_to_login(&req, login_page))
+}
+
+/// The response refusing a logged out user, redirecting them to `login_page` unless the
+/// request came from there.
+fn redirect_to_login(req: &Request<Body>, login_page: &str) -> Response<Body> {
+    let headers = req.headers();
+    let base_url: Option<&str> =
+        headers
+            .get(http::header::REFERER)
+            .and_then(|referer| match referer.to_str() {
+                Ok(referer_str) => {
+                    headers
+                        .get(http::header::ORIGIN)
+                        .and_then(|origin| match origin.to_str() {
+                            Ok(origin_str) => referer_str.strip_prefix(origin_str),
+                            Err(_) => None,
+                        })
+                }
+                Err(_) => None,
+            });
+
+    if let Some(base_url) = base_url {
+        // Redirect if the request did not originated from the login page
+        if base_url != login_page {
+            leptos_axum::redirect(login_page);
         }
+    }
 
-        return Err(Response::builder().body(Body::empty()).unwrap());
+    Response::builder().body(Body::empty()).unwrap()
+}
+
+// Takes the session by value, a `&Request` held across the await would make the
+// middleware future `!Send`
+async fn second_factor_pending(session: Option<Session>) -> bool {
+    let Some(session) = session else {
+        return false;
+    };
+    session
+        .get::<auth::PendingSecondFactor>(auth::PENDING_SECOND_FACTOR_KEY)
+        .await
+        .is_ok_and(|pending| pending.is_some())
+}
+
+/// Authenticates scripts by the API key or JWT in `Authorization: Bearer`, see
+/// [`require_api_key`] and [`require_jwt`]. Other requests go through
+/// [`require_session_login`].
+pub async fn require_app_login(req: Request<Body>) -> Result<Request<Body>, Response<Body>> {
+    match bearer_token(&req) {
+        Some(token) if auth::api_keys::is_api_key(token) => require_api_key(req).await,
+        Some(_) => require_jwt(req).await,
+        None => require_session_login(req).await,
     }
+}
 
+/// Authenticates machine clients by the API key in `Authorization: Bearer <key>`, without
+/// a session. Inserts [`auth::BearerAuth`] and the same [`auth::UserId`] as [`auth_role`].
+pub async fn require_api_key(mut req: Request<Body>) -> Result<Request<Body>, Response<Body>> {
+    let Some(auth_session) = req.extensions().get::<auth::AuthSession>() else {
+        return Err(internal_error());
+    };
+    let Some(key) = bearer_token(&req) else {
+        return Err(bearer_challenge(None));
+    };
+
+    let (user, api_key) = match auth_session.backend.authenticate_api_key(key).await {
+        Ok(Some(authenticated)) => authenticated,
+        Ok(None) => return Err(bearer_challenge(Some("invalid_token"))),
+        Err(_) => return Err(internal_error()),
+    };
+    let scopes = match api_key.scopes {
+        Some(scopes) => match auth_session
+            .backend
+            .scoped_permissions(&user, &scopes)
+            .await
+        {
+            Ok(permissions) => Some(permissions),
+            Err(_) => return Err(internal_error()),
+        },
+        None => None,
+    };
+
+    req.extensions_mut().insert(auth::UserId(user.id()));
+    req.extensions_mut()
+        .insert::<auth::BearerAuth>(auth::BearerAuth { user, scopes });
     Ok(req)
 }
 
-pub async fn auth_role(
+/// Authenticates API clients by the JWT access token in `Authorization: Bearer <token>`.
+/// The token is checked by its signature alone, without a store or session lookup.
+/// Inserts [`auth::BearerAuth`] and the same [`auth::UserId`] as [`auth_role`].
+pub async fn require_jwt(mut req: Request<Body>) -> Result<Request<Body>, Response<Body>> {
+    let Some(auth_session) = req.extensions().get::<auth::AuthSession>() else {
+        return Err(internal_error());
+    };
+    let Some(token) = bearer_token(&req) else {
+        return Err(bearer_challenge(None));
+    };
+
+    let user = match auth_session.backend.authenticate_jwt(token) {
+        Ok(user) => user,
+        Err(auth::Error::InvalidToken | auth::Error::JwtDisabled) => {
+            return Err(bearer_challenge(Some("invalid_token")))
+        }
+        Err(_) => return Err(internal_error()),
+    };
+
+    req.extensions_mut().insert(auth::UserId(user.id()));
+    req.extensions_mut()
+        .insert::<auth::BearerAuth>(auth::BearerAuth { user, scopes: None });
+    Ok(req)
+}
+
+/// Authenticates internal tools with a username and password in `Authorization: Basic`.
+/// Accounts with two-factor authentication are refused, a password alone is not enough
+/// for them. Inserts [`auth::BearerAuth`] and the same [`auth::UserId`] as [`auth_role`].
+pub async fn require_basic_auth(mut req: Request<Body>) -> Result<Request<Body>, Response<Body>> {
+    let Some(auth_session) = req.extensions().get::<auth::AuthSession>() else {
+        return Err(internal_error());
+    };
+    let Some((username, password)) = basic_credentials(&req) else {
+        return Err(basic_challenge());
+    };
+
+    let user = match auth_session
+        .backend
+        .authenticate(auth::Credentials::Password { username, password })
+        .await
+    {
+        Ok(Some(user)) if user.totp_secret.is_none() => user,
+        Ok(_) => return Err(basic_challenge()),
+        Err(_) => return Err(internal_error()),
+    };
+
+    req.extensions_mut().insert(auth::UserId(user.id()));
+    req.extensions_mut()
+        .insert::<auth::BearerAuth>(auth::BearerAuth { user, scopes: None });
+    Ok(req)
+}
+
+/// Authenticates clients by the certificate they presented during the TLS handshake, see
+/// [`crate::tls::serve`]. The certificate was already verified against the client CA,
+/// this maps it to an existing user. Inserts [`auth::BearerAuth`] and the same
+/// [`auth::UserId`] as [`auth_role`].
+pub async fn require_client_cert(mut req: Request<Body>) -> Result<Request<Body>, Response<Body>> {
+    use auth::UserStore;
+
+    let Some(auth_session) = req.extensions().get::<auth::AuthSession>() else {
+        return Err(internal_error());
+    };
+    let Some(certificate) = req.extensions().get::<ClientCertificate>() else {
+        return Err(server_fn_error(
+            StatusCode::UNAUTHORIZED,
+            "A client certificate is required",
+        ));
+    };
+    let source = req
+        .extensions()
+        .get::<Arc<Config>>()
+        .map(|config| config.tls.username_from)
+        .unwrap_or_default();
+    let Some(username) = certificate.username(source) else {
+        return Err(server_fn_error(
+            StatusCode::FORBIDDEN,
+            "The client certificate names no user",
+        ));
+    };
+
+    let user = match auth_session
+        .backend
+        .store()
+        .get_user_by_username(username)
+        .await
+    {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            return Err(server_fn_error(
+                StatusCode::FORBIDDEN,
+                "The client certificate names an unknown user",
+            ))
+        }
+        Err(_) => return Err(internal_error()),
+    };
+
+    req.extensions_mut().insert(auth::UserId(user.id()));
+    req.extensions_mut()
+        .insert::<auth::BearerAuth>(auth::BearerAuth { user, scopes: None });
+    Ok(req)
+}
+
+fn basic_credentials(req: &Request<Body>) -> Option<(String, String)> {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    let encoded = req
+        .headers()
+        .get(http::header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+    Some((username.into(), password.into()))
+}
+
+/// A 401 asking for a username and password, see RFC 7617.
+fn basic_challenge() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::UNAUTHORIZED)
+        .header(
+            http::header::WWW_AUTHENTICATE,
+            "Basic realm=\"Leptos Auth\", charset=\"UTF-8\"",
+        )
+        .body(Body::empty())
+        .unwrap()
+}
+
+fn bearer_token(req: &Request<Body>) -> Option<&str> {
+    req.headers()
+        .get(http::header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+/// A 401 telling the client to authenticate with a bearer token, see RFC 6750.
+fn bearer_challenge(error: Option<&str>) -> Response<Body> {
+    let challenge = match error {
+        Some(error) => format!("Bearer error=\"{error}\""),
+        None => "Bearer".into(),
+    };
+    Response::builder()
+        .status(StatusCode::UNAUTHORIZED)
+        .header(http::header::WWW_AUTHENTICATE, challenge)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// The user behind a request, authenticated by a bearer credential or by the session.
+fn request_user<B>(req: &Request<Body>) -> Option<&B::User>
+where
+    B: AuthnBackend + AuthzBackend + 'static,
+{
+    if let Some(bearer) = req
+        .extensions()
+        .get::<auth::BearerAuth<B::User, B::Permission>>()
+    {
+        return Some(&bearer.user);
+    }
+    req.extensions()
+        .get::<axum_login::AuthSession<B>>()?
+        .user
+        .as_ref()
+}
+
+/// Logs in the user named by a trusted reverse proxy, see [`auth::proxy`]. Runs for every
+/// request between the auth layer and the routes, so [`require_login`] and [`auth_role`]
+/// see an ordinary session. Requests without the header pass through untouched.
+pub async fn proxy_auth(
     mut req: Request<Body>,
-    role: auth::Role,
+    proxy: Arc<auth::proxy::ProxyAuth>,
 ) -> Result<Request<Body>, Response<Body>> {
-    let Some(auth_session) = req.extensions().get::<auth::AuthSession>() else {
+    if !req.headers().contains_key(proxy.header()) {
+        return Ok(req);
+    }
+    let trusted = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .is_some_and(|ConnectInfo(peer)| proxy.is_trusted(peer.ip()));
+    if !trusted {
+        // Anyone can send the header, only the proxy is believed
+        req.headers_mut().remove(proxy.header());
+        return Ok(req);
+    }
+
+    let Some(username) = req
+        .headers()
+        .get(proxy.header())
+        .and_then(|username| username.to_str().ok())
+        .map(str::to_owned)
+    else {
         return Err(Response::builder()
-            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .status(StatusCode::BAD_REQUEST)
             .body(Body::empty())
             .unwrap());
     };
+    let Some(auth_session) = req.extensions_mut().get_mut::<auth::AuthSession>() else {
+        return Err(internal_error());
+    };
+    if auth_session
+        .user
+        .as_ref()
+        .is_some_and(|user| user.username == username)
+    {
+        return Ok(req);
+    }
 
-    let is_authorized = {
-        if let Some(user) = &auth_session.user {
-            if auth_session
-                .backend
-                .has_perm(user, role.into())
-                .await
-                .unwrap_or(false)
-            {
-                Some(auth::UserId(user.id()))
-            } else {
-                None
-            }
-        } else {
-            None
+    let roles = [auth::Role::User.into()];
+    let user = match auth_session
+        .backend
+        .find_or_provision_proxy_user(&username, proxy.provision().then_some(&roles[..]))
+        .await
+    {
+        Ok(Some(user)) => user,
+        Ok(None) | Err(auth::Error::InvalidUsername(_)) => {
+            return Err(Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .body(Body::empty())
+                .unwrap())
         }
+        Err(_) => return Err(internal_error()),
+    };
+    if auth_session.login(&user).await.is_err() {
+        return Err(internal_error());
+    }
+
+    Ok(req)
+}
+
+/// Refuses users who have not verified their account yet. Use after [`require_login`].
+pub async fn require_verified(req: Request<Body>) -> Result<Request<Body>, Response<Body>> {
+    if req.extensions().get::<auth::AuthSession>().is_none() {
+        return Err(internal_error());
+    }
+
+    match request_user::<auth::AppBackend>(&req) {
+        Some(user) if user.verified => Ok(req),
+        Some(_) => Err(server_fn_error(
+            StatusCode::FORBIDDEN,
+            crate::verification::NOT_VERIFIED,
+        )),
+        None => Err(Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .body(Body::empty())
+            .unwrap()),
+    }
+}
+
+/// A response the server function client decodes as `ServerFnError::ServerError(msg)`.
+fn server_fn_error(status: StatusCode, msg: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(
+            ServerFnError::new(msg).ser().unwrap_or_default(),
+        ))
+        .unwrap()
+}
+
+/// The empty 500 response of a middleware that cannot run, e.g. without the auth layer,
+/// or whose backend failed.
+fn internal_error() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::INTERNAL_SERVER_ERROR)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Lets the request through if the user has `permission`, for any axum-login backend `B`,
+/// e.g. `|req| auth_role::<MyBackend>(req, MyRole::Admin.permission())` in
+/// `compose_from_fn!`. With [`auth::Backend`] roles are permissions too, see
+/// [`auth::AuthRole::permission`]. Inserts the [`auth::UserId`] of the user.
+pub async fn auth_role<B>(
+    mut req: Request<Body>,
+    permission: impl Into<B::Permission>,
+) -> Result<Request<Body>, Response<Body>>
+where
+    B: AuthnBackend + AuthzBackend + 'static,
+    axum_login::UserId<B>: Display,
+{
+    let permission: B::Permission = permission.into();
+    let Some(auth_session) = req.extensions().get::<axum_login::AuthSession<B>>() else {
+        return Err(internal_error());
+    };
+    let Some(user) = request_user::<B>(&req) else {
+        return Err(Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .body(Body::empty())
+            .unwrap());
     };
 
-    if is_authorized.is_none() {
+    // Scoped tokens only get the part of the user's permissions they were given
+    let in_scope = req
+        .extensions()
+        .get::<auth::BearerAuth<B::User, B::Permission>>()
+        .and_then(|bearer| bearer.scopes.as_ref())
+        .is_none_or(|scopes| scopes.contains(&permission));
+    let is_authorized = in_scope
+        && auth_session
+            .backend
+            .has_perm(user, permission)
+            .await
+            .unwrap_or(false);
+    if !is_authorized {
         return Err(Response::builder()
             .status(StatusCode::UNAUTHORIZED)
             .body(Body::empty())
             .unwrap());
     }
 
-    req.extensions_mut()
-        .insert::<auth::UserId>(is_authorized.unwrap());
-
+    let user_id = auth::UserId(user.id().to_string());
+    req.extensions_mut().insert(user_id);
     Ok(req)
 }
+
+/// [`auth_role`] for [`auth::Backend`] with `role`, [`auth::Role`] or any other
+/// [`auth::AuthRole`].
+pub async fn require_role(
+    req: Request<Body>,
+    role: impl auth::AuthRole,
+) -> Result<Request<Body>, Response<Body>> {
+    auth_role::<auth::AppBackend>(req, role.permission()).await
+}
+
+/// [`auth_role`] for [`auth::Backend`] with a named permission, held through the user's
+/// roles or groups, e.g. `|req| require_permission(req, auth::permissions::GROUPS_MANAGE)`.
+pub async fn require_permission(
+    req: Request<Body>,
+    permission: auth::Permission,
+) -> Result<Request<Body>, Response<Body>> {
+    auth_role::<auth::AppBackend>(req, permission).await
+}
@@ -0,0 +1,253 @@
+use crate::server_error_message;
+use leptos::*;
+use leptos_router::*;
+use serde::{Deserialize, Serialize};
+
+/// Session key of the [`crate::auth::oidc::AuthorizationRequest`] until the provider calls back
+#[cfg(feature = "ssr")]
+const PENDING_OIDC_LOGIN_KEY: &str = "auth.pending_oidc_login";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OidcProviderInfo {
+    pub name: String,
+    pub display_name: String,
+}
+
+/// One login button per configured provider.
+#[island]
+pub fn OidcLogin() -> impl IntoView {
+    let start_action = create_server_action::<StartOidcLogin>();
+    let providers = create_local_resource(|| (), |_| list_oidc_providers());
+    let error = move || {
+        start_action
+            .value()
+            .get()
+            .and_then(|res| res.err())
+            .map(|err| server_error_message(&err))
+    };
+
+    create_effect(move |_| {
+        if let Some(Ok(url)) = start_action.value().get() {
+            let _ = window().location().set_href(&url);
+        }
+    });
+
+    view! {
+        <Suspense fallback=|| ()>
+            {move || providers.get().and_then(|res| res.ok()).map(|providers| {
+                providers.into_iter().map(|provider| view! {
+                    <ActionForm action=start_action>
+                        <input type="hidden" name="provider" value=provider.name/>
+                        <button class="button-auth" type="submit">
+                            "Login with " {provider.display_name}
+                        </button>
+                    </ActionForm>
+                }).collect_view()
+            })}
+        </Suspense>
+        <Show when=move || error().is_some()>
+            <p class="uk-text-danger">{error}</p>
+        </Show>
+    }
+}
+
+#[component]
+pub fn OidcCallbackPage() -> impl IntoView {
+    let params = use_params_map();
+    let query = use_query_map();
+    let provider =
+        params.with_untracked(|params| params.get("provider").cloned().unwrap_or_default());
+    let (code, state, error) = query.with_untracked(|query| {
+        (
+            query.get("code").cloned().unwrap_or_default(),
+            query.get("state").cloned().unwrap_or_default(),
+            query.get("error").cloned(),
+        )
+    });
+
+    view! {
+        <OidcCallback provider code state error/>
+    }
+}
+
+#[island]
+fn OidcCallback(
+    provider: String,
+    code: String,
+    state: String,
+    error: Option<String>,
+) -> impl IntoView {
+    let finish_action = create_server_action::<FinishOidcLogin>();
+    let refused = error.is_some();
+    let error = create_memo(move |_| {
+        error
+            .clone()
+            .map(|error| format!("The provider refused the login: {error}"))
+            .or_else(|| {
+                finish_action
+                    .value()
+                    .get()
+                    .and_then(|res| res.err())
+                    .map(|err| server_error_message(&err))
+            })
+    });
+
+    // The code is short-lived, redeem it right away
+    create_effect(move |_| {
+        if !refused {
+            finish_action.dispatch(FinishOidcLogin {
+                provider: provider.clone(),
+                code: code.clone(),
+                state: state.clone(),
+            });
+        }
+    });
+    create_effect(move |_| {
+        if let Some(Ok(next)) = finish_action.value().get() {
+            let _ = window().location().set_href(&next);
+        }
+    });
+
+    view! {
+        <Show
+            when=move || error().is_some()
+            fallback=|| view! { <div uk-spinner></div> }
+        >
+            <p class="uk-text-danger">{error}</p>
+            <a href="/">"Back to login"</a>
+        </Show>
+    }
+}
+
+#[server(ListOidcProviders)]
+pub(crate) async fn list_oidc_providers() -> Result<Vec<OidcProviderInfo>, ServerFnError> {
+    use crate::auth::oidc::OidcProviders;
+    use axum::Extension;
+    use std::sync::Arc;
+
+    let Extension(providers) = leptos_axum::extract::<Extension<Arc<OidcProviders>>>().await?;
+    Ok(providers
+        .iter()
+        .map(|provider| OidcProviderInfo {
+            name: provider.name().into(),
+            display_name: provider.display_name().into(),
+        })
+        .collect())
+}
+
+#[server(StartOidcLogin)]
+async fn start_oidc_login(provider: String) -> Result<String, ServerFnError> {
+    start_authorization(&provider, false).await
+}
+
+/// Sends the logged in user to `provider`, to link their account there.
+#[server(StartOidcLink)]
+#[middleware(crate::compose_from_fn!(crate::require_session_login))]
+pub(crate) async fn start_oidc_link(provider: String) -> Result<String, ServerFnError> {
+    start_authorization(&provider, true).await
+}
+
+#[cfg(feature = "ssr")]
+async fn start_authorization(provider: &str, link: bool) -> Result<String, ServerFnError> {
+    use crate::auth::oidc::OidcProviders;
+    use axum::Extension;
+    use axum_login::tower_sessions::Session;
+    use std::sync::Arc;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(providers) = leptos_axum::extract::<Extension<Arc<OidcProviders>>>().await?;
+    let Extension(session) = leptos_axum::extract::<Extension<Session>>().await?;
+    let Some(provider) = providers.get(provider) else {
+        res.set_status(http::StatusCode::NOT_FOUND);
+        return Err(ServerFnError::new("Unknown provider"));
+    };
+
+    let (url, mut request) = match provider.authorization_url().await {
+        Ok(authorization) => authorization,
+        Err(err) => {
+            eprintln!(
+                "OpenID Connect discovery for {} failed: {err}",
+                provider.name()
+            );
+            res.set_status(http::StatusCode::BAD_GATEWAY);
+            return Err(ServerFnError::new("The provider is unavailable"));
+        }
+    };
+    request.link = link;
+    session.insert(PENDING_OIDC_LOGIN_KEY, request).await?;
+    leptos_axum::redirect(&url);
+
+    Ok(url)
+}
+
+#[server(FinishOidcLogin)]
+async fn finish_oidc_login(
+    provider: String,
+    code: String,
+    state: String,
+) -> Result<String, ServerFnError> {
+    use crate::auth::oidc::{AuthorizationRequest, OidcProviders};
+    use crate::auth::{AuthSession, Error, Role};
+    use axum::Extension;
+    use axum_login::tower_sessions::Session;
+    use std::sync::Arc;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(mut auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let Extension(providers) = leptos_axum::extract::<Extension<Arc<OidcProviders>>>().await?;
+    let Extension(session) = leptos_axum::extract::<Extension<Session>>().await?;
+
+    // Taken out up front, so a callback can only be answered once
+    let request = session
+        .remove::<AuthorizationRequest>(PENDING_OIDC_LOGIN_KEY)
+        .await?
+        .filter(|request| request.provider == provider && request.state == state);
+    let (Some(request), Some(provider)) = (request, providers.get(&provider)) else {
+        res.set_status(http::StatusCode::BAD_REQUEST);
+        return Err(ServerFnError::new("The login request expired, try again"));
+    };
+
+    let claims = match provider.exchange_code(&request, &code).await {
+        Ok(claims) => claims,
+        Err(err) => {
+            eprintln!(
+                "OpenID Connect login with {} failed: {err}",
+                provider.name()
+            );
+            res.set_status(http::StatusCode::UNAUTHORIZED);
+            return Err(ServerFnError::new(
+                "The answer of the provider could not be verified",
+            ));
+        }
+    };
+
+    if request.link {
+        let Some(user) = &auth_session.user else {
+            res.set_status(http::StatusCode::UNAUTHORIZED);
+            return Err(ServerFnError::new("Log in before linking an account"));
+        };
+        return match auth_session
+            .backend
+            .link_external_identity(user, provider.name(), &claims.sub)
+            .await
+        {
+            Ok(()) => Ok("/account/logins".into()),
+            Err(err @ Error::IdentityInUse) => {
+                res.set_status(http::StatusCode::CONFLICT);
+                Err(ServerFnError::new(err))
+            }
+            Err(_) => {
+                res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+                Err(ServerFnError::ServerError("".to_string()))
+            }
+        };
+    }
+
+    let user = auth_session
+        .backend
+        .find_or_provision_external_user(provider.name(), &claims, &[Role::User.into()])
+        .await?;
+
+    let next = crate::complete_login(&mut auth_session, &user).await?;
+    Ok(next.into())
+}
@@ -0,0 +1,96 @@
+use axum::async_trait;
+use std::path::PathBuf;
+use std::sync::Arc;
+use time::OffsetDateTime;
+
+use crate::config::{OutboxConfig, OutboxKind};
+
+/// A message for a user, e.g. a password reset link.
+#[derive(Debug, Clone)]
+pub struct Message {
+    /// Username of the recipient
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers [`Message`]s to users. Swap in a mail or SMS transport for production,
+/// the built-in ones only need a terminal or a writable directory.
+#[async_trait]
+pub trait Outbox: Send + Sync + 'static {
+    async fn send(&self, message: Message) -> std::io::Result<()>;
+}
+
+/// The outbox picked at startup, shared through a request extension. A named type
+/// instead of `Arc<dyn Outbox>`, so server functions sending through it stay `Send`.
+#[derive(Clone)]
+pub struct SharedOutbox(Arc<dyn Outbox>);
+
+impl SharedOutbox {
+    pub fn new(outbox: impl Outbox) -> Self {
+        Self(Arc::new(outbox))
+    }
+
+    pub async fn send(&self, message: Message) -> std::io::Result<()> {
+        self.0.send(message).await
+    }
+}
+
+pub fn from_config(config: &OutboxConfig) -> SharedOutbox {
+    match config.kind {
+        OutboxKind::Console => SharedOutbox::new(ConsoleOutbox),
+        OutboxKind::File => SharedOutbox::new(FileOutbox::new(&config.dir)),
+    }
+}
+
+/// Prints messages to stdout.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConsoleOutbox;
+
+#[async_trait]
+impl Outbox for ConsoleOutbox {
+    async fn send(&self, message: Message) -> std::io::Result<()> {
+        println!(
+            "To: {}\nSubject: {}\n\n{}\n",
+            message.to, message.subject, message.body
+        );
+        Ok(())
+    }
+}
+
+/// Writes every message to its own file in `dir`.
+#[derive(Debug, Clone)]
+pub struct FileOutbox {
+    dir: PathBuf,
+}
+
+impl FileOutbox {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+#[async_trait]
+impl Outbox for FileOutbox {
+    async fn send(&self, message: Message) -> std::io::Result<()> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        // The recipient must not pick the directory, e.g. with `../`
+        let recipient: String = message
+            .to
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '@' => c,
+                _ => '_',
+            })
+            .collect();
+        let file_name = format!(
+            "{}-{recipient}.txt",
+            OffsetDateTime::now_utc().unix_timestamp_nanos(),
+        );
+        let contents = format!(
+            "To: {}\nSubject: {}\n\n{}\n",
+            message.to, message.subject, message.body
+        );
+        tokio::fs::write(self.dir.join(file_name), contents).await
+    }
+}
@@ -0,0 +1,55 @@
+// Bridges the JSON challenges of the server to `navigator.credentials`, which
+// expects and returns binary fields as ArrayBuffers instead of base64url strings.
+
+const decode = (value) =>
+  Uint8Array.from(atob(value.replace(/-/g, "+").replace(/_/g, "/")), (c) => c.charCodeAt(0));
+
+const encode = (buffer) =>
+  btoa(String.fromCharCode(...new Uint8Array(buffer)))
+    .replace(/\+/g, "-")
+    .replace(/\//g, "_")
+    .replace(/=+$/, "");
+
+export async function createPasskey(options) {
+  const { publicKey } = JSON.parse(options);
+  publicKey.challenge = decode(publicKey.challenge);
+  publicKey.user.id = decode(publicKey.user.id);
+  for (const credential of publicKey.excludeCredentials ?? []) {
+    credential.id = decode(credential.id);
+  }
+
+  const credential = await navigator.credentials.create({ publicKey });
+  return JSON.stringify({
+    id: credential.id,
+    rawId: encode(credential.rawId),
+    type: credential.type,
+    response: {
+      attestationObject: encode(credential.response.attestationObject),
+      clientDataJSON: encode(credential.response.clientDataJSON),
+    },
+    extensions: credential.getClientExtensionResults(),
+  });
+}
+
+export async function getPasskey(options) {
+  const { publicKey } = JSON.parse(options);
+  publicKey.challenge = decode(publicKey.challenge);
+  for (const credential of publicKey.allowCredentials ?? []) {
+    credential.id = decode(credential.id);
+  }
+
+  const credential = await navigator.credentials.get({ publicKey });
+  const { response } = credential;
+  return JSON.stringify({
+    id: credential.id,
+    rawId: encode(credential.rawId),
+    type: credential.type,
+    response: {
+      authenticatorData: encode(response.authenticatorData),
+      clientDataJSON: encode(response.clientDataJSON),
+      signature: encode(response.signature),
+      userHandle: response.userHandle ? encode(response.userHandle) : null,
+    },
+    extensions: credential.getClientExtensionResults(),
+  });
+}
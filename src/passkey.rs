@@ -0,0 +1,366 @@
+use crate::server_error_message;
+use leptos::*;
+use leptos_router::*;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+/// Session key of the registration state, until the browser answers the challenge
+#[cfg(feature = "ssr")]
+const PENDING_PASSKEY_REGISTRATION_KEY: &str = "auth.pending_passkey_registration";
+/// Session key of a [`PendingPasskeyLogin`]
+#[cfg(feature = "ssr")]
+const PENDING_PASSKEY_LOGIN_KEY: &str = "auth.pending_passkey_login";
+
+const CEREMONY_FAILED: &str = "The passkey request was cancelled or failed";
+
+#[wasm_bindgen(module = "/src/passkey.js")]
+extern "C" {
+    #[wasm_bindgen(catch, js_name = createPasskey)]
+    async fn create_passkey(options: String) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(catch, js_name = getPasskey)]
+    async fn get_passkey(options: String) -> Result<JsValue, JsValue>;
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Serialize, Deserialize)]
+struct PendingPasskeyLogin {
+    user_id: String,
+    state: crate::auth::passkey::PasskeyAuthentication,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasskeyInfo {
+    pub id: String,
+    pub name: String,
+    /// Creation date, e.g. `2024-05-01`
+    pub created: String,
+}
+
+/// Runs a passkey login for `username` in the browser.
+pub(crate) async fn login_with_passkey(username: String) -> Result<(), String> {
+    let options = start_passkey_login(username)
+        .await
+        .map_err(|err| server_error_message(&err))?;
+    let credential = get_passkey(options)
+        .await
+        .map_err(|_| CEREMONY_FAILED.to_string())?;
+    finish_passkey_login(credential.as_string().unwrap_or_default())
+        .await
+        .map_err(|err| server_error_message(&err))
+}
+
+async fn register_passkey(name: String) -> Result<(), String> {
+    let options = start_passkey_registration()
+        .await
+        .map_err(|err| server_error_message(&err))?;
+    let credential = create_passkey(options)
+        .await
+        .map_err(|_| CEREMONY_FAILED.to_string())?;
+    finish_passkey_registration(name, credential.as_string().unwrap_or_default())
+        .await
+        .map_err(|err| server_error_message(&err))
+}
+
+#[component]
+pub fn PasskeySettingsPage() -> impl IntoView {
+    view! {
+        <PasskeySettings/>
+        <a href="/protected">"Back"</a>
+    }
+}
+
+#[island]
+fn PasskeySettings() -> impl IntoView {
+    let delete_action = create_server_action::<DeletePasskey>();
+    let (registered, set_registered) = create_signal(0);
+    let (error, set_error) = create_signal(None::<String>);
+    let name_input = create_node_ref::<html::Input>();
+    let passkeys = create_local_resource(
+        move || (registered.get(), delete_action.version().get()),
+        |_| list_passkeys(),
+    );
+    let delete_error = move || {
+        delete_action
+            .value()
+            .get()
+            .and_then(|res| res.err())
+            .map(|err| server_error_message(&err))
+    };
+
+    let on_register = move |_| {
+        let name = name_input
+            .get()
+            .map(|input| input.value())
+            .unwrap_or_default();
+        spawn_local(async move {
+            match register_passkey(name).await {
+                Ok(()) => {
+                    set_error(None);
+                    set_registered.update(|n| *n += 1);
+                }
+                Err(err) => set_error(Some(err)),
+            }
+        });
+    };
+
+    view! {
+        <Suspense fallback=|| view! { <div uk-spinner></div> }>
+            {move || passkeys.get().map(|passkeys| match passkeys {
+                Ok(passkeys) if passkeys.is_empty() => view! {
+                    <p>"You have no passkeys yet."</p>
+                }.into_view(),
+                Ok(passkeys) => view! {
+                    <ul class="uk-list">
+                        {passkeys.into_iter().map(|passkey| view! {
+                            <li>
+                                {passkey.name} " (added " {passkey.created} ")"
+                                <ActionForm action=delete_action>
+                                    <input type="hidden" name="id" value=passkey.id/>
+                                    <button class="uk-button uk-button-small" type="submit">
+                                        "Remove"
+                                    </button>
+                                </ActionForm>
+                            </li>
+                        }).collect_view()}
+                    </ul>
+                }.into_view(),
+                Err(err) => server_error_message(&err).into_view(),
+            })}
+        </Suspense>
+        <Show when=move || delete_error().is_some()>
+            <p class="uk-text-danger">{delete_error}</p>
+        </Show>
+        <div class="uk-margin">
+            <input class="uk-input uk-form-width-medium" type="text" node_ref=name_input
+                placeholder="Passkey name, e.g. Laptop" maxlength="64"/>
+        </div>
+        <Show when=move || error().is_some()>
+            <p class="uk-text-danger">{error}</p>
+        </Show>
+        <button class="button-auth" type="button" on:click=on_register>
+            "Add a passkey"
+        </button>
+    }
+}
+
+#[server(StartPasskeyLogin)]
+async fn start_passkey_login(username: String) -> Result<String, ServerFnError> {
+    use crate::auth::{AuthSession, UserStore};
+    use axum::Extension;
+    use axum_login::tower_sessions::Session;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let Extension(session) = leptos_axum::extract::<Extension<Session>>().await?;
+
+    let challenge = match auth_session
+        .backend
+        .store()
+        .get_user_by_username(&username)
+        .await?
+    {
+        Some(user) => auth_session
+            .backend
+            .start_passkey_authentication(&user.id.0)
+            .await?
+            .map(|(challenge, state)| (user.id.0, challenge, state)),
+        None => None,
+    };
+    let Some((user_id, challenge, state)) = challenge else {
+        res.set_status(http::StatusCode::UNAUTHORIZED);
+        return Err(ServerFnError::new(
+            "No passkey is registered for this account",
+        ));
+    };
+
+    session
+        .insert(
+            PENDING_PASSKEY_LOGIN_KEY,
+            PendingPasskeyLogin { user_id, state },
+        )
+        .await?;
+    Ok(serde_json::to_string(&challenge)?)
+}
+
+#[server(FinishPasskeyLogin)]
+async fn finish_passkey_login(credential: String) -> Result<(), ServerFnError> {
+    use crate::auth::{AuthSession, Credentials};
+    use axum::Extension;
+    use axum_login::tower_sessions::Session;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(mut auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let Extension(session) = leptos_axum::extract::<Extension<Session>>().await?;
+
+    // Taken out up front, so a challenge can only be answered once
+    let Some(pending) = session
+        .remove::<PendingPasskeyLogin>(PENDING_PASSKEY_LOGIN_KEY)
+        .await?
+    else {
+        res.set_status(http::StatusCode::UNAUTHORIZED);
+        return Err(ServerFnError::new("Start the passkey login again"));
+    };
+    let Ok(credential) = serde_json::from_str(&credential) else {
+        res.set_status(http::StatusCode::BAD_REQUEST);
+        return Err(ServerFnError::new(CEREMONY_FAILED));
+    };
+
+    let user = auth_session
+        .authenticate(Credentials::Passkey {
+            user_id: pending.user_id,
+            credential: Box::new(credential),
+            state: Box::new(pending.state),
+        })
+        .await?;
+    let Some(user) = user else {
+        res.set_status(http::StatusCode::UNAUTHORIZED);
+        return Err(ServerFnError::new("The passkey could not be verified"));
+    };
+
+    // A passkey already requires user verification on the authenticator,
+    // so it is not followed by the TOTP step
+    if auth_session.login(&user).await.is_err() {
+        res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+        return Err(ServerFnError::ServerError("".to_string()));
+    }
+
+    Ok(())
+}
+
+#[server(StartPasskeyRegistration)]
+#[middleware(crate::compose_from_fn!(crate::require_session_login))]
+async fn start_passkey_registration() -> Result<String, ServerFnError> {
+    use crate::auth::AuthSession;
+    use axum::Extension;
+    use axum_login::tower_sessions::Session;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let Extension(session) = leptos_axum::extract::<Extension<Session>>().await?;
+    let Some(user) = auth_session.user else {
+        res.set_status(http::StatusCode::UNAUTHORIZED);
+        return Err(ServerFnError::new(""));
+    };
+
+    let (challenge, state) = auth_session
+        .backend
+        .start_passkey_registration(&user)
+        .await?;
+    session
+        .insert(PENDING_PASSKEY_REGISTRATION_KEY, state)
+        .await?;
+    Ok(serde_json::to_string(&challenge)?)
+}
+
+#[server(FinishPasskeyRegistration)]
+#[middleware(crate::compose_from_fn!(crate::require_session_login))]
+async fn finish_passkey_registration(
+    name: String,
+    credential: String,
+) -> Result<(), ServerFnError> {
+    use crate::auth::{passkey::PasskeyRegistration, AuthSession};
+    use axum::Extension;
+    use axum_login::tower_sessions::Session;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let Extension(session) = leptos_axum::extract::<Extension<Session>>().await?;
+    let Some(user) = auth_session.user else {
+        res.set_status(http::StatusCode::UNAUTHORIZED);
+        return Err(ServerFnError::new(""));
+    };
+
+    let name = name.trim();
+    let name = if name.is_empty() { "Passkey" } else { name };
+    if name.chars().count() > 64 {
+        res.set_status(http::StatusCode::BAD_REQUEST);
+        return Err(ServerFnError::new("The name must be at most 64 characters"));
+    }
+
+    let Some(state) = session
+        .remove::<PasskeyRegistration>(PENDING_PASSKEY_REGISTRATION_KEY)
+        .await?
+    else {
+        res.set_status(http::StatusCode::BAD_REQUEST);
+        return Err(ServerFnError::new("Start adding the passkey again"));
+    };
+    let Ok(credential) = serde_json::from_str(&credential) else {
+        res.set_status(http::StatusCode::BAD_REQUEST);
+        return Err(ServerFnError::new(CEREMONY_FAILED));
+    };
+
+    if auth_session
+        .backend
+        .finish_passkey_registration(&user, name, &credential, &state)
+        .await
+        .is_err()
+    {
+        res.set_status(http::StatusCode::BAD_REQUEST);
+        return Err(ServerFnError::new("The passkey could not be verified"));
+    }
+
+    Ok(())
+}
+
+#[server(ListPasskeys)]
+#[middleware(crate::compose_from_fn!(crate::require_session_login))]
+async fn list_passkeys() -> Result<Vec<PasskeyInfo>, ServerFnError> {
+    use crate::auth::{AuthSession, UserStore};
+    use axum::Extension;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let Some(user) = auth_session.user else {
+        res.set_status(http::StatusCode::UNAUTHORIZED);
+        return Err(ServerFnError::new(""));
+    };
+
+    let passkeys = auth_session
+        .backend
+        .store()
+        .get_passkeys(&user.id.0)
+        .await?;
+    Ok(passkeys
+        .into_iter()
+        .map(|passkey| PasskeyInfo {
+            id: passkey.id,
+            name: passkey.name,
+            created: passkey.created_at.date().to_string(),
+        })
+        .collect())
+}
+
+#[server(DeletePasskey)]
+#[middleware(crate::compose_from_fn!(crate::require_session_login))]
+async fn delete_passkey(id: String) -> Result<(), ServerFnError> {
+    use crate::auth::{AuthSession, Error, LoginMethod};
+    use axum::Extension;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let Some(user) = auth_session.user else {
+        res.set_status(http::StatusCode::UNAUTHORIZED);
+        return Err(ServerFnError::new(""));
+    };
+
+    match auth_session
+        .backend
+        .unlink_login_method(&user, &LoginMethod::Passkey { id })
+        .await
+    {
+        Ok(()) => Ok(()),
+        Err(err @ Error::UnknownLoginMethod) => {
+            res.set_status(http::StatusCode::NOT_FOUND);
+            Err(ServerFnError::new(err))
+        }
+        Err(err @ Error::LastLoginMethod) => {
+            res.set_status(http::StatusCode::BAD_REQUEST);
+            Err(ServerFnError::new(err))
+        }
+        Err(_) => {
+            res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(ServerFnError::ServerError("".to_string()))
+        }
+    }
+}
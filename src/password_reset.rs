@@ -0,0 +1,167 @@
+use crate::server_error_message;
+use leptos::*;
+use leptos_router::*;
+
+#[component]
+pub fn ForgotPasswordPage() -> impl IntoView {
+    view! {
+        <ForgotPassword/>
+        <a href="/">"Back to login"</a>
+    }
+}
+
+#[component]
+pub fn ResetPasswordPage() -> impl IntoView {
+    let params = use_params_map();
+    let token = params.with_untracked(|params| params.get("token").cloned().unwrap_or_default());
+
+    view! {
+        <ResetPassword token/>
+    }
+}
+
+#[island]
+fn ForgotPassword() -> impl IntoView {
+    let request_action = create_server_action::<RequestPasswordReset>();
+
+    view! {
+        <Show
+            when=move || request_action.value().get().is_some_and(|v| v.is_ok())
+            fallback=move || view! {
+                <ActionForm action=request_action>
+                    <div class="uk-margin">
+                        <input class="uk-input uk-form-width-medium" type="text"
+                            name="username" placeholder="Username" autocomplete="username" required/>
+                    </div>
+                    <button class="button-auth uk-button-large" type="submit">
+                        "Send reset link"
+                    </button>
+                </ActionForm>
+            }
+        >
+            <p>"If the account exists, a reset link is on its way."</p>
+        </Show>
+    }
+}
+
+#[island]
+fn ResetPassword(token: String) -> impl IntoView {
+    let reset_action = create_server_action::<ResetPasswordSFn>();
+    let error = move || {
+        reset_action
+            .value()
+            .get()
+            .and_then(|res| res.err())
+            .map(|err| server_error_message(&err))
+    };
+
+    view! {
+        <ActionForm action=reset_action>
+            <input type="hidden" name="token" value=token/>
+            <div class="uk-margin">
+                <input class="uk-input uk-form-width-medium" type="password"
+                    name="password" placeholder="New password" autocomplete="new-password" required/>
+            </div>
+            <div class="uk-margin">
+                <input class="uk-input uk-form-width-medium" type="password"
+                    name="password_confirmation" placeholder="Confirm password"
+                    autocomplete="new-password" required/>
+            </div>
+            <Show when=move || error().is_some()>
+                <p class="uk-text-danger">{error}</p>
+            </Show>
+            <button class="button-auth uk-button-large" type="submit">
+                "Set password"
+            </button>
+        </ActionForm>
+    }
+}
+
+#[server(RequestPasswordReset)]
+async fn request_password_reset(username: String) -> Result<(), ServerFnError> {
+    use crate::auth::{AuthSession, TokenKind, UserStore};
+    use crate::config::Config;
+    use crate::outbox::{Message, SharedOutbox};
+    use axum::Extension;
+    use std::sync::Arc;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let Extension(config) = leptos_axum::extract::<Extension<Arc<Config>>>().await?;
+    let Extension(outbox) = leptos_axum::extract::<Extension<SharedOutbox>>().await?;
+
+    // Unknown usernames get the same answer, so this can't be used to probe for accounts
+    let user = match auth_session
+        .backend
+        .store()
+        .get_user_by_username(&username)
+        .await
+    {
+        Ok(Some(user)) => user,
+        Ok(None) => return Ok(()),
+        Err(_) => {
+            res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(ServerFnError::ServerError("".to_string()));
+        }
+    };
+
+    let Ok(token) = auth_session
+        .backend
+        .issue_token(
+            &user.id.0,
+            TokenKind::PasswordReset,
+            time::Duration::minutes(30),
+        )
+        .await
+    else {
+        res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+        return Err(ServerFnError::ServerError("".to_string()));
+    };
+
+    let message = Message {
+        to: user.username,
+        subject: "Reset your password".into(),
+        body: format!(
+            "Follow this link within 30 minutes to choose a new password:\n{}/reset/{token}",
+            config.public_url.trim_end_matches('/')
+        ),
+    };
+    if let Err(err) = outbox.send(message).await {
+        res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+        return Err(ServerFnError::ServerError(err.to_string()));
+    }
+
+    Ok(())
+}
+
+#[server(ResetPasswordSFn)]
+async fn reset_password(
+    token: String,
+    password: String,
+    password_confirmation: String,
+) -> Result<(), ServerFnError> {
+    use crate::auth::{AuthSession, Error};
+    use axum::Extension;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    if password != password_confirmation {
+        res.set_status(http::StatusCode::BAD_REQUEST);
+        return Err(ServerFnError::new("Passwords do not match"));
+    }
+
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    match auth_session.backend.reset_password(&token, &password).await {
+        Ok(_) => {}
+        Err(err @ (Error::InvalidToken | Error::WeakPassword(_))) => {
+            res.set_status(http::StatusCode::BAD_REQUEST);
+            return Err(ServerFnError::new(err));
+        }
+        Err(_) => {
+            res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(ServerFnError::ServerError("".to_string()));
+        }
+    }
+    leptos_axum::redirect("/");
+
+    Ok(())
+}
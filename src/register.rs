@@ -0,0 +1,100 @@
+use crate::server_error_message;
+use leptos::*;
+use leptos_router::*;
+
+#[component]
+pub fn RegisterPage() -> impl IntoView {
+    view! {
+        <Register/>
+        <a href="/">"Already have an account? Login"</a>
+    }
+}
+
+#[island]
+fn Register() -> impl IntoView {
+    let register_action = create_server_action::<RegisterSFn>();
+    let error = move || {
+        register_action
+            .value()
+            .get()
+            .and_then(|res| res.err())
+            .map(|err| server_error_message(&err))
+    };
+
+    view! {
+        <ActionForm action=register_action>
+            <div class="uk-margin">
+                <input class="uk-input uk-form-width-medium" type="text"
+                    name="username" placeholder="Username" autocomplete="username" required/>
+            </div>
+            <div class="uk-margin">
+                <input class="uk-input uk-form-width-medium" type="password"
+                    name="password" placeholder="Password" autocomplete="new-password" required/>
+            </div>
+            <div class="uk-margin">
+                <input class="uk-input uk-form-width-medium" type="password"
+                    name="password_confirmation" placeholder="Confirm password"
+                    autocomplete="new-password" required/>
+            </div>
+            <Show when=move || error().is_some()>
+                <p class="uk-text-danger">{error}</p>
+            </Show>
+            <button class="button-auth uk-button-large" type="submit">
+                "Register"
+            </button>
+        </ActionForm>
+    }
+}
+
+#[server(RegisterSFn)]
+async fn register(
+    username: String,
+    password: String,
+    password_confirmation: String,
+) -> Result<(), ServerFnError> {
+    use crate::auth::{AuthSession, Error, Role};
+    use axum::Extension;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    if password != password_confirmation {
+        res.set_status(http::StatusCode::BAD_REQUEST);
+        return Err(ServerFnError::new("Passwords do not match"));
+    }
+
+    let Extension(mut auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let user = match auth_session
+        .backend
+        .register_user(&username, &password, &[Role::User.into()])
+        .await
+    {
+        Ok(user) => user,
+        Err(err @ Error::UsernameTaken) => {
+            res.set_status(http::StatusCode::CONFLICT);
+            return Err(ServerFnError::new(err));
+        }
+        Err(err @ (Error::InvalidUsername(_) | Error::WeakPassword(_))) => {
+            res.set_status(http::StatusCode::BAD_REQUEST);
+            return Err(ServerFnError::new(err));
+        }
+        Err(_) => {
+            res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(ServerFnError::ServerError("".to_string()));
+        }
+    };
+
+    // The account exists at this point, a failed delivery can be retried from the protected page
+    if let Err(err) =
+        crate::verification::send_verification_link(&auth_session.backend, &user).await
+    {
+        eprintln!("Failed to send verification link: {err}");
+    }
+
+    // Sign the new account in right away
+    if auth_session.login(&user).await.is_err() {
+        res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+        return Err(ServerFnError::ServerError("".to_string()));
+    }
+    leptos_axum::redirect("/protected");
+
+    Ok(())
+}
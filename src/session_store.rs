@@ -0,0 +1,198 @@
+use axum::async_trait;
+use axum_login::tower_sessions::{
+    session::{Id, Record},
+    session_store, ExpiredDeletion, MemoryStore, SessionStore,
+};
+use sqlx::SqlitePool;
+use std::time::Duration;
+use time::OffsetDateTime;
+
+use crate::auth;
+use crate::config::{SessionConfig, SessionStoreKind};
+
+/// Session store picked from [`SessionConfig`].
+#[derive(Clone, Debug)]
+pub enum AppSessionStore {
+    Memory(MemoryStore),
+    Sqlite(SqliteSessionStore),
+}
+
+impl AppSessionStore {
+    /// SQLite sessions share `pool`, the one of the user store, and only connect to
+    /// `database_url` themselves without it.
+    pub async fn from_config(
+        config: &SessionConfig,
+        pool: Option<&SqlitePool>,
+        database_url: &str,
+    ) -> Result<Self, auth::Error> {
+        Ok(match config.store {
+            SessionStoreKind::Memory => Self::Memory(MemoryStore::default()),
+            SessionStoreKind::Sqlite => {
+                let pool = match pool {
+                    Some(pool) => pool.clone(),
+                    None => auth::SqliteStore::connect(database_url)
+                        .await?
+                        .pool()
+                        .clone(),
+                };
+                Self::Sqlite(SqliteSessionStore::new(pool))
+            }
+        })
+    }
+
+    /// Spawns a task deleting expired sessions every `period`.
+    /// The memory store drops expired sessions on load and has nothing to sweep.
+    pub fn spawn_expired_deletion(&self, period: Duration) {
+        let Self::Sqlite(store) = self else {
+            return;
+        };
+
+        let store = store.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                if let Err(err) = store.delete_expired().await {
+                    eprintln!("Failed to delete expired sessions: {err}");
+                }
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl SessionStore for AppSessionStore {
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        match self {
+            Self::Memory(store) => store.create(record).await,
+            Self::Sqlite(store) => store.create(record).await,
+        }
+    }
+
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        match self {
+            Self::Memory(store) => store.save(record).await,
+            Self::Sqlite(store) => store.save(record).await,
+        }
+    }
+
+    async fn load(&self, session_id: &Id) -> session_store::Result<Option<Record>> {
+        match self {
+            Self::Memory(store) => store.load(session_id).await,
+            Self::Sqlite(store) => store.load(session_id).await,
+        }
+    }
+
+    async fn delete(&self, session_id: &Id) -> session_store::Result<()> {
+        match self {
+            Self::Memory(store) => store.delete(session_id).await,
+            Self::Sqlite(store) => store.delete(session_id).await,
+        }
+    }
+}
+
+/// Keeps sessions in the `sessions` table so they survive restarts and can be
+/// shared between processes using the same database.
+#[derive(Clone, Debug)]
+pub struct SqliteSessionStore {
+    pool: SqlitePool,
+}
+
+impl SqliteSessionStore {
+    /// Expects the migrations to have been run on `pool`, see [`auth::SqliteStore::migrate`].
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+fn backend_error(err: sqlx::Error) -> session_store::Error {
+    session_store::Error::Backend(err.to_string())
+}
+
+#[async_trait]
+impl SessionStore for SqliteSessionStore {
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        let data = serde_json::to_string(&record.data)
+            .map_err(|err| session_store::Error::Encode(err.to_string()))?;
+
+        loop {
+            let res = sqlx::query("INSERT INTO sessions (id, data, expiry_date) VALUES (?, ?, ?)")
+                .bind(record.id.to_string())
+                .bind(&data)
+                .bind(record.expiry_date.unix_timestamp())
+                .execute(&self.pool)
+                .await;
+
+            match res {
+                Ok(_) => return Ok(()),
+                // Session id collision, pick another one
+                Err(sqlx::Error::Database(err)) if err.is_unique_violation() => {
+                    record.id = Id::default();
+                }
+                Err(err) => return Err(backend_error(err)),
+            }
+        }
+    }
+
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        let data = serde_json::to_string(&record.data)
+            .map_err(|err| session_store::Error::Encode(err.to_string()))?;
+
+        sqlx::query(
+            "INSERT INTO sessions (id, data, expiry_date) VALUES (?, ?, ?)
+             ON CONFLICT (id) DO UPDATE SET data = excluded.data, expiry_date = excluded.expiry_date",
+        )
+        .bind(record.id.to_string())
+        .bind(data)
+        .bind(record.expiry_date.unix_timestamp())
+        .execute(&self.pool)
+        .await
+        .map_err(backend_error)?;
+
+        Ok(())
+    }
+
+    async fn load(&self, session_id: &Id) -> session_store::Result<Option<Record>> {
+        let row = sqlx::query_as::<_, (String, i64)>(
+            "SELECT data, expiry_date FROM sessions WHERE id = ? AND expiry_date > ?",
+        )
+        .bind(session_id.to_string())
+        .bind(OffsetDateTime::now_utc().unix_timestamp())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(backend_error)?;
+
+        let Some((data, expiry_date)) = row else {
+            return Ok(None);
+        };
+
+        Ok(Some(Record {
+            id: *session_id,
+            data: serde_json::from_str(&data)
+                .map_err(|err| session_store::Error::Decode(err.to_string()))?,
+            expiry_date: OffsetDateTime::from_unix_timestamp(expiry_date)
+                .map_err(|err| session_store::Error::Decode(err.to_string()))?,
+        }))
+    }
+
+    async fn delete(&self, session_id: &Id) -> session_store::Result<()> {
+        sqlx::query("DELETE FROM sessions WHERE id = ?")
+            .bind(session_id.to_string())
+            .execute(&self.pool)
+            .await
+            .map_err(backend_error)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ExpiredDeletion for SqliteSessionStore {
+    async fn delete_expired(&self) -> session_store::Result<()> {
+        sqlx::query("DELETE FROM sessions WHERE expiry_date <= ?")
+            .bind(OffsetDateTime::now_utc().unix_timestamp())
+            .execute(&self.pool)
+            .await
+            .map_err(backend_error)?;
+        Ok(())
+    }
+}
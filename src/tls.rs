@@ -0,0 +1,176 @@
+use axum::extract::ConnectInfo;
+use axum::Router;
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use std::io::BufReader;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::{VerifierBuilderError, WebPkiClientVerifier};
+use tokio_rustls::rustls::{self, RootCertStore, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+use tower::ServiceExt;
+use x509_parser::extensions::GeneralName;
+
+use crate::config::{CertUsernameSource, TlsConfig};
+
+/// Time a client gets to complete the handshake, so silent connections don't pile up.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, thiserror::Error)]
+pub enum TlsError {
+    #[error("failed to read {0}: {1}")]
+    Io(String, std::io::Error),
+    #[error("no private key in {0}")]
+    NoPrivateKey(String),
+    #[error(transparent)]
+    Rustls(#[from] rustls::Error),
+    #[error(transparent)]
+    ClientVerifier(#[from] VerifierBuilderError),
+}
+
+/// The verified certificate a client connected with, a request extension.
+/// See `middlewares::require_client_cert`.
+#[derive(Debug, Clone)]
+pub struct ClientCertificate {
+    /// Distinguished name of the subject, e.g. `CN=alice, O=Example`
+    pub subject: String,
+    pub common_name: Option<String>,
+    /// Email addresses in the subject alternative name
+    pub emails: Vec<String>,
+    /// DNS names in the subject alternative name
+    pub dns_names: Vec<String>,
+}
+
+impl ClientCertificate {
+    /// Reads the parts used to find the user, `None` if `der` is not an X.509 certificate.
+    pub fn from_der(der: &[u8]) -> Option<Self> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+        let common_name = cert
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .map(str::to_owned);
+
+        let mut emails = Vec::new();
+        let mut dns_names = Vec::new();
+        if let Ok(Some(san)) = cert.subject_alternative_name() {
+            for name in &san.value.general_names {
+                match name {
+                    GeneralName::RFC822Name(email) => emails.push(email.to_string()),
+                    GeneralName::DNSName(dns_name) => dns_names.push(dns_name.to_string()),
+                    _ => {}
+                }
+            }
+        }
+
+        Some(Self {
+            subject: cert.subject().to_string(),
+            common_name,
+            emails,
+            dns_names,
+        })
+    }
+
+    /// The username the certificate stands for.
+    pub fn username(&self, source: CertUsernameSource) -> Option<&str> {
+        match source {
+            CertUsernameSource::CommonName => self.common_name.as_deref(),
+            CertUsernameSource::SanEmail => self.emails.first()?.split('@').next(),
+            CertUsernameSource::SanDns => self.dns_names.first().map(String::as_str),
+        }
+    }
+}
+
+/// Builds the rustls config from [`TlsConfig`]. With a client CA, client certificates
+/// are requested and must chain up to it, the handshake fails otherwise.
+pub fn server_config(config: &TlsConfig) -> Result<Arc<ServerConfig>, TlsError> {
+    let provider = Arc::new(ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()?;
+
+    let builder = match &config.client_ca_file {
+        Some(client_ca_file) => {
+            let mut roots = RootCertStore::empty();
+            for cert in read_certs(client_ca_file)? {
+                roots.add(cert)?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+            let verifier = if config.client_cert_optional {
+                verifier.allow_unauthenticated().build()?
+            } else {
+                verifier.build()?
+            };
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut server_config =
+        builder.with_single_cert(read_certs(&config.cert_file)?, read_key(&config.key_file)?)?;
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Arc::new(server_config))
+}
+
+fn read_certs(path: &str) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let file = std::fs::File::open(path).map_err(|err| TlsError::Io(path.into(), err))?;
+    rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<_, _>>()
+        .map_err(|err| TlsError::Io(path.into(), err))
+}
+
+fn read_key(path: &str) -> Result<PrivateKeyDer<'static>, TlsError> {
+    let file = std::fs::File::open(path).map_err(|err| TlsError::Io(path.into(), err))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|err| TlsError::Io(path.into(), err))?
+        .ok_or_else(|| TlsError::NoPrivateKey(path.into()))
+}
+
+/// Serves `app` over TLS, in place of `axum::serve`. Requests carry the peer address as
+/// [`ConnectInfo`], like with `into_make_service_with_connect_info`, and the
+/// [`ClientCertificate`] if the client sent one.
+pub async fn serve(listener: TcpListener, app: Router, server_config: Arc<ServerConfig>) {
+    let acceptor = TlsAcceptor::from(server_config);
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(err) => {
+                eprintln!("Failed to accept a connection: {err}");
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+
+        tokio::spawn(async move {
+            // Fails for clients with an untrusted certificate, among others
+            let Ok(Ok(stream)) =
+                tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await
+            else {
+                return;
+            };
+            let client_certificate = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .and_then(|cert| ClientCertificate::from_der(cert));
+
+            let service = hyper::service::service_fn(move |mut req: http::Request<Incoming>| {
+                req.extensions_mut().insert(ConnectInfo(peer));
+                if let Some(client_certificate) = &client_certificate {
+                    req.extensions_mut().insert(client_certificate.clone());
+                }
+                app.clone().oneshot(req)
+            });
+            // Errors are dropped connections, nothing to answer
+            let _ = hyper_util::server::conn::auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await;
+        });
+    }
+}
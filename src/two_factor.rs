@@ -0,0 +1,392 @@
+use crate::server_error_message;
+use leptos::*;
+use leptos_router::*;
+use serde::{Deserialize, Serialize};
+
+/// Session key of the secret shown during enrollment, until the user confirms it
+#[cfg(feature = "ssr")]
+const PENDING_TOTP_SECRET_KEY: &str = "auth.pending_totp_secret";
+#[cfg(feature = "ssr")]
+const TOTP_ISSUER: &str = "Leptos Auth";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TotpEnrollment {
+    pub secret: String,
+    pub qr_svg: String,
+}
+
+#[component]
+pub fn SecondFactorPage() -> impl IntoView {
+    view! {
+        <SecondFactor/>
+    }
+}
+
+#[component]
+pub fn TwoFactorSettingsPage() -> impl IntoView {
+    view! {
+        <TwoFactorSettings/>
+        <a href="/protected">"Back"</a>
+    }
+}
+
+#[island]
+fn SecondFactor() -> impl IntoView {
+    let verify_action = create_server_action::<VerifySecondFactor>();
+    let error = move || {
+        verify_action
+            .value()
+            .get()
+            .and_then(|res| res.err())
+            .map(|err| server_error_message(&err))
+    };
+
+    view! {
+        <ActionForm action=verify_action>
+            <div class="uk-margin">
+                <input class="uk-input uk-form-width-medium" type="text" name="code"
+                    placeholder="Authentication or recovery code"
+                    autocomplete="one-time-code" required/>
+            </div>
+            <Show when=move || error().is_some()>
+                <p class="uk-text-danger">{error}</p>
+            </Show>
+            <button class="button-auth uk-button-large" type="submit">
+                "Verify"
+            </button>
+        </ActionForm>
+    }
+}
+
+#[island]
+fn TwoFactorSettings() -> impl IntoView {
+    let start_action = create_server_action::<StartTotpEnrollment>();
+    let confirm_action = create_server_action::<ConfirmTotpEnrollment>();
+    let disable_action = create_server_action::<DisableTotp>();
+    let regenerate_action = create_server_action::<RegenerateRecoveryCodes>();
+    let enabled = create_local_resource(
+        move || {
+            (
+                confirm_action.version().get(),
+                disable_action.version().get(),
+            )
+        },
+        |_| two_factor_status(),
+    );
+    let error = move || {
+        confirm_action
+            .value()
+            .get()
+            .and_then(|res| res.err())
+            .or_else(|| disable_action.value().get().and_then(|res| res.err()))
+            .or_else(|| regenerate_action.value().get().and_then(|res| res.err()))
+            .map(|err| server_error_message(&err))
+    };
+    let enrollment = move || start_action.value().get().and_then(|res| res.ok());
+    let recovery_codes = move || {
+        regenerate_action
+            .value()
+            .get()
+            .or_else(|| confirm_action.value().get())
+            .and_then(|res| res.ok())
+    };
+
+    let code_input = || {
+        view! {
+            <div class="uk-margin">
+                <input class="uk-input uk-form-width-medium" type="text" name="code"
+                    placeholder="Authentication code" inputmode="numeric"
+                    autocomplete="one-time-code" required/>
+            </div>
+        }
+    };
+
+    view! {
+        <Suspense fallback=|| view! { <div uk-spinner></div> }>
+            {move || enabled.get().map(|enabled| match enabled {
+                Ok(true) => view! {
+                    <p>"Two-factor authentication is on."</p>
+                    <ActionForm action=disable_action>
+                        {code_input()}
+                        <button class="button-auth" type="submit">"Turn off"</button>
+                    </ActionForm>
+                    <ActionForm action=regenerate_action>
+                        {code_input()}
+                        <button class="button-auth" type="submit">"New recovery codes"</button>
+                    </ActionForm>
+                }.into_view(),
+                Ok(false) => view! {
+                    <Show
+                        when=move || enrollment().is_some()
+                        fallback=move || view! {
+                            <ActionForm action=start_action>
+                                <button class="button-auth" type="submit">
+                                    "Set up two-factor authentication"
+                                </button>
+                            </ActionForm>
+                        }
+                    >
+                        {move || enrollment().map(|enrollment| view! {
+                            <p>"Scan this code with your authenticator app:"</p>
+                            <div inner_html=enrollment.qr_svg></div>
+                            <p>"Or enter this key: " <code>{enrollment.secret}</code></p>
+                        })}
+                        <ActionForm action=confirm_action>
+                            {code_input()}
+                            <button class="button-auth" type="submit">"Confirm"</button>
+                        </ActionForm>
+                    </Show>
+                }.into_view(),
+                Err(err) => server_error_message(&err).into_view(),
+            })}
+            <Show when=move || error().is_some()>
+                <p class="uk-text-danger">{error}</p>
+            </Show>
+            {move || recovery_codes().map(|codes| view! { <RecoveryCodeList codes/> })}
+        </Suspense>
+    }
+}
+
+#[component]
+fn RecoveryCodeList(codes: Vec<String>) -> impl IntoView {
+    view! {
+        <p>
+            "Recovery codes, each one logs you in once without your authenticator app. "
+            "Keep them somewhere safe, they won't be shown again."
+        </p>
+        <ul class="uk-list">
+            {codes.into_iter().map(|code| view! { <li><code>{code}</code></li> }).collect_view()}
+        </ul>
+    }
+}
+
+#[server(VerifySecondFactor)]
+async fn verify_second_factor(code: String) -> Result<(), ServerFnError> {
+    use crate::auth::{AuthSession, Error, PendingSecondFactor, PENDING_SECOND_FACTOR_KEY};
+    use axum::Extension;
+    use axum_login::tower_sessions::Session;
+    use axum_login::AuthnBackend;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(mut auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let Extension(session) = leptos_axum::extract::<Extension<Session>>().await?;
+
+    let Some(pending) = session
+        .get::<PendingSecondFactor>(PENDING_SECOND_FACTOR_KEY)
+        .await?
+    else {
+        leptos_axum::redirect("/");
+        res.set_status(http::StatusCode::UNAUTHORIZED);
+        return Err(ServerFnError::new("Log in with your password first"));
+    };
+
+    let user = auth_session.backend.get_user(&pending.user_id).await?;
+    let Some(user) = user.filter(|user| user.totp_secret.is_some()) else {
+        session
+            .remove::<PendingSecondFactor>(PENDING_SECOND_FACTOR_KEY)
+            .await?;
+        leptos_axum::redirect("/");
+        res.set_status(http::StatusCode::UNAUTHORIZED);
+        return Err(ServerFnError::new("Log in with your password first"));
+    };
+
+    // A recovery code stands in for the authenticator app, and is consumed on success
+    match auth_session
+        .backend
+        .verify_second_factor(&user, &code)
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => {
+            res.set_status(http::StatusCode::UNAUTHORIZED);
+            return Err(ServerFnError::new("Invalid code"));
+        }
+        Err(err @ Error::SecondFactorLocked) => {
+            res.set_status(http::StatusCode::TOO_MANY_REQUESTS);
+            return Err(ServerFnError::new(err));
+        }
+        Err(_) => {
+            res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(ServerFnError::ServerError("".to_string()));
+        }
+    }
+
+    session
+        .remove::<PendingSecondFactor>(PENDING_SECOND_FACTOR_KEY)
+        .await?;
+    if auth_session.login(&user).await.is_err() {
+        res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+        return Err(ServerFnError::ServerError("".to_string()));
+    }
+    leptos_axum::redirect("/protected");
+
+    Ok(())
+}
+
+#[server(TwoFactorStatus)]
+#[middleware(crate::compose_from_fn!(crate::require_session_login))]
+async fn two_factor_status() -> Result<bool, ServerFnError> {
+    use crate::auth::AuthSession;
+    use axum::Extension;
+
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    Ok(auth_session
+        .user
+        .is_some_and(|user| user.totp_secret.is_some()))
+}
+
+#[server(StartTotpEnrollment)]
+#[middleware(crate::compose_from_fn!(crate::require_session_login))]
+async fn start_totp_enrollment() -> Result<TotpEnrollment, ServerFnError> {
+    use crate::auth::{totp, AuthSession};
+    use axum::Extension;
+    use axum_login::tower_sessions::Session;
+    use qrcode::{render::svg, QrCode};
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let Extension(session) = leptos_axum::extract::<Extension<Session>>().await?;
+    let Some(user) = auth_session.user else {
+        res.set_status(http::StatusCode::UNAUTHORIZED);
+        return Err(ServerFnError::new(""));
+    };
+    if user.totp_secret.is_some() {
+        res.set_status(http::StatusCode::BAD_REQUEST);
+        return Err(ServerFnError::new(
+            "Two-factor authentication is already on",
+        ));
+    }
+
+    let secret = totp::generate_secret();
+    let uri = totp::otpauth_uri(TOTP_ISSUER, &user.username, &secret);
+    let qr_svg = QrCode::new(uri)?
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .build();
+    session.insert(PENDING_TOTP_SECRET_KEY, &secret).await?;
+
+    Ok(TotpEnrollment { secret, qr_svg })
+}
+
+#[server(ConfirmTotpEnrollment)]
+#[middleware(crate::compose_from_fn!(crate::require_session_login))]
+async fn confirm_totp_enrollment(code: String) -> Result<Vec<String>, ServerFnError> {
+    use crate::auth::{totp, AuthSession, UserStore};
+    use axum::Extension;
+    use axum_login::tower_sessions::Session;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let Extension(session) = leptos_axum::extract::<Extension<Session>>().await?;
+    let Some(user) = auth_session.user else {
+        res.set_status(http::StatusCode::UNAUTHORIZED);
+        return Err(ServerFnError::new(""));
+    };
+    let Some(secret) = session.get::<String>(PENDING_TOTP_SECRET_KEY).await? else {
+        res.set_status(http::StatusCode::BAD_REQUEST);
+        return Err(ServerFnError::new("Start the setup again"));
+    };
+
+    let Some(step) = totp::verify(&secret, &code) else {
+        res.set_status(http::StatusCode::BAD_REQUEST);
+        return Err(ServerFnError::new("Invalid code"));
+    };
+
+    let store = auth_session.backend.store();
+    store.set_totp_secret(&user.id.0, Some(&secret)).await?;
+    // The code that confirmed the secret cannot log in too
+    store.use_totp_step(&user.id.0, step).await?;
+    session.remove::<String>(PENDING_TOTP_SECRET_KEY).await?;
+
+    Ok(auth_session
+        .backend
+        .regenerate_recovery_codes(&user.id.0)
+        .await?)
+}
+
+#[server(DisableTotp)]
+#[middleware(crate::compose_from_fn!(crate::require_session_login))]
+async fn disable_totp(code: String) -> Result<(), ServerFnError> {
+    use crate::auth::{AuthSession, Error, UserStore};
+    use axum::Extension;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let Some(user) = auth_session.user else {
+        res.set_status(http::StatusCode::UNAUTHORIZED);
+        return Err(ServerFnError::new(""));
+    };
+    if user.totp_secret.is_none() {
+        res.set_status(http::StatusCode::BAD_REQUEST);
+        return Err(ServerFnError::new("Two-factor authentication is off"));
+    }
+
+    // Asking for a code keeps a hijacked session from turning the second factor off
+    match auth_session.backend.verify_totp(&user, &code).await {
+        Ok(true) => {}
+        Ok(false) => {
+            res.set_status(http::StatusCode::BAD_REQUEST);
+            return Err(ServerFnError::new("Invalid code"));
+        }
+        Err(err @ Error::SecondFactorLocked) => {
+            res.set_status(http::StatusCode::TOO_MANY_REQUESTS);
+            return Err(ServerFnError::new(err));
+        }
+        Err(_) => {
+            res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(ServerFnError::ServerError("".to_string()));
+        }
+    }
+
+    auth_session
+        .backend
+        .store()
+        .set_totp_secret(&user.id.0, None)
+        .await?;
+    auth_session
+        .backend
+        .store()
+        .replace_recovery_codes(&user.id.0, &[])
+        .await?;
+
+    Ok(())
+}
+
+#[server(RegenerateRecoveryCodes)]
+#[middleware(crate::compose_from_fn!(crate::require_session_login))]
+async fn regenerate_recovery_codes(code: String) -> Result<Vec<String>, ServerFnError> {
+    use crate::auth::{AuthSession, Error};
+    use axum::Extension;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let Some(user) = auth_session.user else {
+        res.set_status(http::StatusCode::UNAUTHORIZED);
+        return Err(ServerFnError::new(""));
+    };
+    if user.totp_secret.is_none() {
+        res.set_status(http::StatusCode::BAD_REQUEST);
+        return Err(ServerFnError::new("Two-factor authentication is off"));
+    }
+
+    match auth_session.backend.verify_totp(&user, &code).await {
+        Ok(true) => {}
+        Ok(false) => {
+            res.set_status(http::StatusCode::BAD_REQUEST);
+            return Err(ServerFnError::new("Invalid code"));
+        }
+        Err(err @ Error::SecondFactorLocked) => {
+            res.set_status(http::StatusCode::TOO_MANY_REQUESTS);
+            return Err(ServerFnError::new(err));
+        }
+        Err(_) => {
+            res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(ServerFnError::ServerError("".to_string()));
+        }
+    }
+
+    Ok(auth_session
+        .backend
+        .regenerate_recovery_codes(&user.id.0)
+        .await?)
+}
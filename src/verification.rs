@@ -0,0 +1,152 @@
+use crate::server_error_message;
+use leptos::*;
+use leptos_router::*;
+
+/// Error message of `require_verified`, lets the client offer a new verification link.
+pub const NOT_VERIFIED: &str = "Account is not verified yet";
+
+#[component]
+pub fn VerifyAccountPage() -> impl IntoView {
+    let params = use_params_map();
+    let token = params.with_untracked(|params| params.get("token").cloned().unwrap_or_default());
+
+    view! {
+        <VerifyAccount token/>
+    }
+}
+
+#[island]
+fn VerifyAccount(token: String) -> impl IntoView {
+    let verify_action = create_server_action::<VerifyAccountSFn>();
+    let error = move || {
+        verify_action
+            .value()
+            .get()
+            .and_then(|res| res.err())
+            .map(|err| server_error_message(&err))
+    };
+
+    view! {
+        <Show
+            when=move || verify_action.value().get().is_some_and(|v| v.is_ok())
+            fallback=move || {
+                let token = token.clone();
+                view! {
+                    <ActionForm action=verify_action>
+                        <input type="hidden" name="token" value=token/>
+                        <Show when=move || error().is_some()>
+                            <p class="uk-text-danger">{error}</p>
+                        </Show>
+                        <button class="button-auth uk-button-large" type="submit">
+                            "Verify account"
+                        </button>
+                    </ActionForm>
+                }
+            }
+        >
+            <p>"Your account is verified."</p>
+            <a href="/protected">"Continue"</a>
+        </Show>
+    }
+}
+
+/// Sends a fresh verification link to the logged in user.
+#[component]
+pub fn ResendVerification() -> impl IntoView {
+    let resend_action = create_server_action::<ResendVerificationSFn>();
+
+    view! {
+        <Show
+            when=move || resend_action.value().get().is_some_and(|v| v.is_ok())
+            fallback=move || view! {
+                <ActionForm action=resend_action>
+                    <button class="uk-button uk-button-small uk-button-default" type="submit">
+                        "Send a new verification link"
+                    </button>
+                </ActionForm>
+            }
+        >
+            <p>"A new verification link is on its way."</p>
+        </Show>
+    }
+}
+
+/// Issues an account verification token for `user` and sends it through the outbox.
+#[cfg(feature = "ssr")]
+pub(crate) async fn send_verification_link(
+    backend: &crate::auth::AppBackend,
+    user: &crate::auth::User,
+) -> Result<(), ServerFnError> {
+    use crate::auth::TokenKind;
+    use crate::config::Config;
+    use crate::outbox::{Message, SharedOutbox};
+    use axum::Extension;
+    use std::sync::Arc;
+
+    let Extension(config) = leptos_axum::extract::<Extension<Arc<Config>>>().await?;
+    let Extension(outbox) = leptos_axum::extract::<Extension<SharedOutbox>>().await?;
+
+    let token = backend
+        .issue_token(
+            &user.id.0,
+            TokenKind::AccountVerification,
+            time::Duration::days(1),
+        )
+        .await?;
+    let message = Message {
+        to: user.username.clone(),
+        subject: "Verify your account".into(),
+        body: format!(
+            "Follow this link within 24 hours to verify your account:\n{}/verify/{token}",
+            config.public_url.trim_end_matches('/')
+        ),
+    };
+    outbox.send(message).await?;
+
+    Ok(())
+}
+
+#[server(VerifyAccountSFn)]
+async fn verify_account(token: String) -> Result<(), ServerFnError> {
+    use crate::auth::{AuthSession, Error};
+    use axum::Extension;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    match auth_session.backend.verify_account(&token).await {
+        Ok(_) => Ok(()),
+        Err(err @ Error::InvalidToken) => {
+            res.set_status(http::StatusCode::BAD_REQUEST);
+            Err(ServerFnError::new(err))
+        }
+        Err(_) => {
+            res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+            Err(ServerFnError::ServerError("".to_string()))
+        }
+    }
+}
+
+#[server(ResendVerificationSFn)]
+#[middleware(crate::compose_from_fn!(crate::require_session_login))]
+async fn resend_verification() -> Result<(), ServerFnError> {
+    use crate::auth::AuthSession;
+    use axum::Extension;
+
+    let res = expect_context::<leptos_axum::ResponseOptions>();
+    let Extension(auth_session) = leptos_axum::extract::<Extension<AuthSession>>().await?;
+    let Some(user) = &auth_session.user else {
+        res.set_status(http::StatusCode::UNAUTHORIZED);
+        return Err(ServerFnError::new(""));
+    };
+    if user.verified {
+        res.set_status(http::StatusCode::BAD_REQUEST);
+        return Err(ServerFnError::new("Account is already verified"));
+    }
+
+    if let Err(err) = send_verification_link(&auth_session.backend, user).await {
+        res.set_status(http::StatusCode::INTERNAL_SERVER_ERROR);
+        return Err(err);
+    }
+
+    Ok(())
+}
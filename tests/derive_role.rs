@@ -0,0 +1,8 @@
+#![cfg(feature = "ssr")]
+
+#[test]
+fn derive_role() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/ui/role_pass.rs");
+    cases.compile_fail("tests/ui/role_fail_*.rs");
+}
@@ -0,0 +1,253 @@
+//! The authorization code flow against a local mock provider.
+
+#![cfg(feature = "ssr")]
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use auth_middleware::auth::oidc::{self, AuthorizationRequest, IdTokenClaims, OidcProvider};
+use auth_middleware::auth::Error;
+use auth_middleware::config::OidcProviderConfig;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Form, Json, Router};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use rcgen::KeyPair;
+use reqwest::Url;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+use tokio::net::TcpListener;
+
+const CLIENT_ID: &str = "test-client";
+
+struct IdpState {
+    issuer: String,
+    key: KeyPair,
+    /// Merged into the claims of every ID token
+    overrides: Value,
+    /// The PKCE challenge and nonce of each issued authorization code
+    codes: HashMap<String, (String, String)>,
+}
+
+/// A provider serving discovery, JWKS and token endpoints. Authorization codes are
+/// issued by [`MockIdp::authorize`] instead of a login page.
+struct MockIdp {
+    issuer: String,
+    state: Arc<Mutex<IdpState>>,
+}
+
+impl MockIdp {
+    async fn start(overrides: Value) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+        let state = Arc::new(Mutex::new(IdpState {
+            issuer: issuer.clone(),
+            key: KeyPair::generate().unwrap(),
+            overrides,
+            codes: HashMap::new(),
+        }));
+
+        let app = Router::new()
+            .route("/.well-known/openid-configuration", get(discovery))
+            .route("/jwks", get(jwks))
+            .route("/token", post(token))
+            .with_state(Arc::clone(&state));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        Self { issuer, state }
+    }
+
+    fn provider(&self) -> OidcProvider {
+        let config = OidcProviderConfig {
+            name: "mock".into(),
+            display_name: "Mock".into(),
+            issuer: self.issuer.clone(),
+            client_id: CLIENT_ID.into(),
+            client_secret: None,
+            scopes: vec!["openid".into()],
+        };
+        OidcProvider::new(config, "http://localhost:3000", reqwest::Client::new())
+    }
+
+    /// What the provider does once the user logged in there: remembers the request
+    /// in `authorization_url` and returns the code for the callback.
+    fn authorize(&self, authorization_url: &str) -> String {
+        let url = Url::parse(authorization_url).unwrap();
+        let params: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(params["response_type"], "code");
+        assert_eq!(params["client_id"], CLIENT_ID);
+        assert_eq!(params["code_challenge_method"], "S256");
+
+        let code = format!("code-{}", params["state"]);
+        self.state.lock().unwrap().codes.insert(
+            code.clone(),
+            (params["code_challenge"].clone(), params["nonce"].clone()),
+        );
+        code
+    }
+}
+
+async fn discovery(State(state): State<Arc<Mutex<IdpState>>>) -> Json<Value> {
+    let issuer = state.lock().unwrap().issuer.clone();
+    Json(json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{issuer}/authorize"),
+        "token_endpoint": format!("{issuer}/token"),
+        "jwks_uri": format!("{issuer}/jwks"),
+    }))
+}
+
+async fn jwks(State(state): State<Arc<Mutex<IdpState>>>) -> Json<Value> {
+    let state = state.lock().unwrap();
+    // Uncompressed P-256 point, 0x04 followed by x and y
+    let point = state.key.public_key_raw();
+    Json(json!({
+        "keys": [{
+            "kty": "EC",
+            "crv": "P-256",
+            "kid": "key-1",
+            "use": "sig",
+            "alg": "ES256",
+            "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+            "y": URL_SAFE_NO_PAD.encode(&point[33..]),
+        }]
+    }))
+}
+
+async fn token(
+    State(state): State<Arc<Mutex<IdpState>>>,
+    Form(form): Form<HashMap<String, String>>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let invalid_grant = || {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "invalid_grant" })),
+        )
+    };
+    let mut state = state.lock().unwrap();
+    if form.get("grant_type").map(String::as_str) != Some("authorization_code")
+        || form.get("client_id").map(String::as_str) != Some(CLIENT_ID)
+    {
+        return Err(invalid_grant());
+    }
+    let (challenge, nonce) = form
+        .get("code")
+        .and_then(|code| state.codes.remove(code))
+        .ok_or_else(invalid_grant)?;
+    let verifier = form.get("code_verifier").ok_or_else(invalid_grant)?;
+    if URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes())) != challenge {
+        return Err(invalid_grant());
+    }
+
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let mut claims = json!({
+        "iss": state.issuer,
+        "aud": CLIENT_ID,
+        "sub": "user-1",
+        "iat": now,
+        "exp": now + 300,
+        "nonce": nonce,
+        "preferred_username": "alice",
+    });
+    for (name, value) in state.overrides.as_object().unwrap() {
+        claims[name] = value.clone();
+    }
+    let mut header = Header::new(Algorithm::ES256);
+    header.kid = Some("key-1".into());
+    let key = EncodingKey::from_ec_pem(state.key.serialize_pem().as_bytes()).unwrap();
+    let id_token = jsonwebtoken::encode(&header, &claims, &key).unwrap();
+
+    Ok(Json(json!({
+        "access_token": "access",
+        "token_type": "Bearer",
+        "id_token": id_token,
+    })))
+}
+
+/// Runs the flow up to the code exchange, `tamper` changes what the client kept.
+async fn login_with(
+    idp: &MockIdp,
+    tamper: impl FnOnce(&mut AuthorizationRequest),
+) -> Result<IdTokenClaims, Error> {
+    let provider = idp.provider();
+    let (url, mut request) = provider.authorization_url().await.unwrap();
+    let code = idp.authorize(&url);
+    tamper(&mut request);
+    provider.exchange_code(&request, &code).await
+}
+
+fn jwt_error(result: Result<IdTokenClaims, Error>) -> ErrorKind {
+    match result {
+        Err(Error::Jwt(err)) => err.into_kind(),
+        other => panic!("expected an ID token error, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn logs_in() {
+    let idp = MockIdp::start(json!({})).await;
+    let claims = login_with(&idp, |_| {}).await.unwrap();
+    assert_eq!(claims.sub, "user-1");
+    assert_eq!(oidc::username_hint(&claims), "alice");
+}
+
+#[tokio::test]
+async fn sends_the_pkce_verifier() {
+    let idp = MockIdp::start(json!({})).await;
+    let result = login_with(&idp, |request| {
+        request.pkce_verifier = "another verifier".into();
+    })
+    .await;
+    assert!(
+        matches!(result, Err(Error::Http(err)) if err.status() == Some(StatusCode::BAD_REQUEST))
+    );
+}
+
+#[tokio::test]
+async fn refuses_another_nonce() {
+    let idp = MockIdp::start(json!({})).await;
+    let result = login_with(&idp, |request| {
+        request.nonce = "another nonce".into();
+    })
+    .await;
+    assert!(matches!(result, Err(Error::Oidc(_))));
+
+    let idp = MockIdp::start(json!({ "nonce": null })).await;
+    assert!(matches!(
+        login_with(&idp, |_| {}).await,
+        Err(Error::Oidc(_))
+    ));
+}
+
+#[tokio::test]
+async fn refuses_another_issuer() {
+    let idp = MockIdp::start(json!({ "iss": "https://issuer.example" })).await;
+    assert_eq!(
+        jwt_error(login_with(&idp, |_| {}).await),
+        ErrorKind::InvalidIssuer
+    );
+}
+
+#[tokio::test]
+async fn refuses_another_audience() {
+    let idp = MockIdp::start(json!({ "aud": "another-client" })).await;
+    assert_eq!(
+        jwt_error(login_with(&idp, |_| {}).await),
+        ErrorKind::InvalidAudience
+    );
+}
+
+#[tokio::test]
+async fn refuses_an_expired_id_token() {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let idp = MockIdp::start(json!({ "iat": now - 900, "exp": now - 600 })).await;
+    assert_eq!(
+        jwt_error(login_with(&idp, |_| {}).await),
+        ErrorKind::ExpiredSignature
+    );
+}
@@ -0,0 +1,178 @@
+//! Passkey ceremonies driven by a software authenticator.
+
+#![cfg(feature = "ssr")]
+
+use std::sync::Arc;
+
+use auth_middleware::auth::passkey::{self, Passkey};
+use auth_middleware::auth::{Backend, Credentials, SqliteStore, StoredPasskey, User, UserStore};
+use axum_login::AuthnBackend;
+use webauthn_authenticator_rs::softpasskey::SoftPasskey;
+use webauthn_authenticator_rs::WebauthnAuthenticator;
+use webauthn_rs::prelude::Url;
+
+const ORIGIN: &str = "http://localhost:3000";
+
+async fn backend() -> Backend<SqliteStore> {
+    let store = SqliteStore::connect("sqlite::memory:").await.unwrap();
+    Backend::new(Arc::new(store)).with_webauthn(passkey::webauthn(ORIGIN, None, "Test").unwrap())
+}
+
+async fn register(
+    backend: &Backend<SqliteStore>,
+    authenticator: &mut WebauthnAuthenticator<SoftPasskey>,
+) -> (User, StoredPasskey) {
+    let user = backend
+        .register_user("alice", "correct horse battery", &[100])
+        .await
+        .unwrap();
+    let (challenge, state) = backend.start_passkey_registration(&user).await.unwrap();
+    let credential = authenticator
+        .do_registration(Url::parse(ORIGIN).unwrap(), challenge)
+        .unwrap();
+    let stored = backend
+        .finish_passkey_registration(&user, "laptop", &credential, &state)
+        .await
+        .unwrap();
+    (user, stored)
+}
+
+/// Answers a fresh login challenge, tampering with the answer first.
+async fn login_with(
+    backend: &Backend<SqliteStore>,
+    authenticator: &mut WebauthnAuthenticator<SoftPasskey>,
+    user: &User,
+    tamper: impl FnOnce(&mut passkey::PublicKeyCredential),
+) -> Option<User> {
+    let (challenge, state) = backend
+        .start_passkey_authentication(&user.id.0)
+        .await
+        .unwrap()
+        .unwrap();
+    let mut credential = authenticator
+        .do_authentication(Url::parse(ORIGIN).unwrap(), challenge)
+        .unwrap();
+    tamper(&mut credential);
+    backend
+        .authenticate(Credentials::Passkey {
+            user_id: user.id.0.clone(),
+            credential: Box::new(credential),
+            state: Box::new(state),
+        })
+        .await
+        .unwrap()
+}
+
+async fn login(
+    backend: &Backend<SqliteStore>,
+    authenticator: &mut WebauthnAuthenticator<SoftPasskey>,
+    user: &User,
+) -> Option<User> {
+    login_with(backend, authenticator, user, |_| {}).await
+}
+
+fn counter(passkey: &Passkey) -> u64 {
+    let passkey = serde_json::to_value(passkey).unwrap();
+    passkey["cred"]["counter"].as_u64().unwrap()
+}
+
+async fn stored_passkey(backend: &Backend<SqliteStore>, user: &User) -> StoredPasskey {
+    let mut passkeys = backend.store().get_passkeys(&user.id.0).await.unwrap();
+    assert_eq!(passkeys.len(), 1);
+    passkeys.pop().unwrap()
+}
+
+#[tokio::test]
+async fn registers_and_logs_in() {
+    let backend = backend().await;
+    let mut authenticator = WebauthnAuthenticator::new(SoftPasskey::new(true));
+    let (user, stored) = register(&backend, &mut authenticator).await;
+    assert_eq!(stored.user_id, user.id.0);
+    assert_eq!(stored.id, passkey::credential_id(stored.passkey.cred_id()));
+    assert_eq!(stored_passkey(&backend, &user).await.name, "laptop");
+
+    let logged_in = login(&backend, &mut authenticator, &user).await.unwrap();
+    assert_eq!(logged_in.id.0, user.id.0);
+}
+
+#[tokio::test]
+async fn excludes_registered_authenticators() {
+    let backend = backend().await;
+    let mut authenticator = WebauthnAuthenticator::new(SoftPasskey::new(true));
+    let (user, stored) = register(&backend, &mut authenticator).await;
+
+    let (challenge, _) = backend.start_passkey_registration(&user).await.unwrap();
+    let excluded = challenge.public_key.exclude_credentials.unwrap();
+    assert_eq!(excluded.len(), 1);
+    assert_eq!(&excluded[0].id, stored.passkey.cred_id());
+}
+
+#[tokio::test]
+async fn stores_the_signature_counter() {
+    let backend = backend().await;
+    let mut authenticator = WebauthnAuthenticator::new(SoftPasskey::new(true));
+    let (user, _) = register(&backend, &mut authenticator).await;
+
+    assert!(login(&backend, &mut authenticator, &user).await.is_some());
+    let first = counter(&stored_passkey(&backend, &user).await.passkey);
+    assert!(first > 0);
+    assert!(login(&backend, &mut authenticator, &user).await.is_some());
+    assert!(counter(&stored_passkey(&backend, &user).await.passkey) > first);
+}
+
+#[tokio::test]
+async fn refuses_a_counter_behind_the_stored_one() {
+    let backend = backend().await;
+    let mut authenticator = WebauthnAuthenticator::new(SoftPasskey::new(true));
+    let (user, _) = register(&backend, &mut authenticator).await;
+    assert!(login(&backend, &mut authenticator, &user).await.is_some());
+
+    // As if a clone of the authenticator had been used many times since
+    let mut stored = stored_passkey(&backend, &user).await;
+    let mut passkey = serde_json::to_value(&stored.passkey).unwrap();
+    passkey["cred"]["counter"] = 1000.into();
+    stored.passkey = serde_json::from_value(passkey).unwrap();
+    backend.store().update_passkey(&stored).await.unwrap();
+
+    assert!(login(&backend, &mut authenticator, &user).await.is_none());
+    assert_eq!(
+        counter(&stored_passkey(&backend, &user).await.passkey),
+        1000
+    );
+}
+
+#[tokio::test]
+async fn refuses_a_bad_signature() {
+    let backend = backend().await;
+    let mut authenticator = WebauthnAuthenticator::new(SoftPasskey::new(true));
+    let (user, _) = register(&backend, &mut authenticator).await;
+
+    let logged_in = login_with(&backend, &mut authenticator, &user, |credential| {
+        let signature = &mut credential.response.signature;
+        let last = signature.len() - 1;
+        signature[last] ^= 0x01;
+    })
+    .await;
+    assert!(logged_in.is_none());
+}
+
+#[tokio::test]
+async fn refuses_an_unknown_authenticator() {
+    let backend = backend().await;
+    let (user, _) = register(
+        &backend,
+        &mut WebauthnAuthenticator::new(SoftPasskey::new(true)),
+    )
+    .await;
+
+    let (challenge, _) = backend
+        .start_passkey_authentication(&user.id.0)
+        .await
+        .unwrap()
+        .unwrap();
+    // Has no key for the allowed credential, so it cannot even answer
+    let mut other = WebauthnAuthenticator::new(SoftPasskey::new(true));
+    assert!(other
+        .do_authentication(Url::parse(ORIGIN).unwrap(), challenge)
+        .is_err());
+}
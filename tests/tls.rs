@@ -0,0 +1,192 @@
+#![cfg(feature = "ssr")]
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use auth_middleware::config::{CertUsernameSource, TlsConfig};
+use auth_middleware::tls::{self, ClientCertificate};
+use axum::routing::get;
+use axum::{Extension, Router};
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa,
+    KeyPair, SanType,
+};
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+struct Ca {
+    cert: Certificate,
+    key: KeyPair,
+}
+
+impl Ca {
+    fn new(name: &str) -> Self {
+        let mut params = CertificateParams::default();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params.distinguished_name.push(DnType::CommonName, name);
+        let key = KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key).unwrap();
+        Self { cert, key }
+    }
+
+    fn client_cert(&self, common_name: &str, email: Option<&str>) -> Issued {
+        let mut params = CertificateParams::default();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, common_name);
+        if let Some(email) = email {
+            params.subject_alt_names = vec![SanType::Rfc822Name(email.try_into().unwrap())];
+        }
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        let key = KeyPair::generate().unwrap();
+        let cert = params.signed_by(&key, &self.cert, &self.key).unwrap();
+        Issued { cert, key }
+    }
+}
+
+struct Issued {
+    cert: Certificate,
+    key: KeyPair,
+}
+
+/// A server trusting `client_ca`, answering with the username of the client certificate.
+struct Server {
+    addr: SocketAddr,
+    server_cert: CertificateDer<'static>,
+    _dir: TempDir,
+}
+
+impl Server {
+    async fn start(client_ca: &Ca, client_cert_optional: bool, source: CertUsernameSource) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let server_key = KeyPair::generate().unwrap();
+        let server_cert = CertificateParams::new(vec!["localhost".to_string()])
+            .unwrap()
+            .self_signed(&server_key)
+            .unwrap();
+
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_owned();
+        std::fs::write(path("server.pem"), server_cert.pem()).unwrap();
+        std::fs::write(path("server.key"), server_key.serialize_pem()).unwrap();
+        std::fs::write(path("client-ca.pem"), client_ca.cert.pem()).unwrap();
+        let config = TlsConfig {
+            enabled: true,
+            cert_file: path("server.pem"),
+            key_file: path("server.key"),
+            client_ca_file: Some(path("client-ca.pem")),
+            client_cert_optional,
+            username_from: source,
+        };
+
+        let app = Router::new().route(
+            "/",
+            get(
+                move |certificate: Option<Extension<ClientCertificate>>| async move {
+                    match certificate {
+                        Some(Extension(certificate)) => certificate
+                            .username(source)
+                            .unwrap_or("no username")
+                            .to_owned(),
+                        None => "anonymous".to_owned(),
+                    }
+                },
+            ),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(tls::serve(
+            listener,
+            app,
+            tls::server_config(&config).unwrap(),
+        ));
+
+        Self {
+            addr,
+            server_cert: server_cert.der().clone(),
+            _dir: dir,
+        }
+    }
+
+    /// The body of `GET /`, `None` if the server hung up.
+    async fn get(&self, client_cert: Option<&Issued>) -> Option<String> {
+        let mut roots = RootCertStore::empty();
+        roots.add(self.server_cert.clone()).unwrap();
+        let builder = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+        let config = match client_cert {
+            Some(issued) => builder
+                .with_client_auth_cert(
+                    vec![issued.cert.der().clone()],
+                    PrivateKeyDer::try_from(issued.key.serialize_der()).unwrap(),
+                )
+                .unwrap(),
+            None => builder.with_no_client_auth(),
+        };
+
+        let stream = TcpStream::connect(self.addr).await.unwrap();
+        let mut stream = TlsConnector::from(Arc::new(config))
+            .connect(ServerName::try_from("localhost").unwrap(), stream)
+            .await
+            .ok()?;
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .ok()?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.ok()?;
+
+        let (head, body) = response.split_once("\r\n\r\n")?;
+        assert!(head.starts_with("HTTP/1.1 200"), "{head}");
+        Some(body.to_owned())
+    }
+}
+
+#[tokio::test]
+async fn accepts_certificate_of_trusted_ca() {
+    let ca = Ca::new("Test CA");
+    let server = Server::start(&ca, false, CertUsernameSource::CommonName).await;
+
+    let alice = ca.client_cert("alice", None);
+    assert_eq!(server.get(Some(&alice)).await.as_deref(), Some("alice"));
+}
+
+#[tokio::test]
+async fn rejects_certificate_of_unknown_ca() {
+    let ca = Ca::new("Test CA");
+    let server = Server::start(&ca, true, CertUsernameSource::CommonName).await;
+
+    let mallory = Ca::new("Other CA").client_cert("alice", None);
+    assert_eq!(server.get(Some(&mallory)).await, None);
+}
+
+#[tokio::test]
+async fn requires_certificate_unless_optional() {
+    let ca = Ca::new("Test CA");
+    let required = Server::start(&ca, false, CertUsernameSource::CommonName).await;
+    assert_eq!(required.get(None).await, None);
+
+    let optional = Server::start(&ca, true, CertUsernameSource::CommonName).await;
+    assert_eq!(optional.get(None).await.as_deref(), Some("anonymous"));
+}
+
+#[tokio::test]
+async fn maps_san_email_to_username() {
+    let ca = Ca::new("Test CA");
+    let server = Server::start(&ca, false, CertUsernameSource::SanEmail).await;
+
+    let alice = ca.client_cert("Alice Example", Some("alice@example.com"));
+    assert_eq!(server.get(Some(&alice)).await.as_deref(), Some("alice"));
+
+    let nameless = ca.client_cert("Bob Example", None);
+    assert_eq!(
+        server.get(Some(&nameless)).await.as_deref(),
+        Some("no username")
+    );
+}
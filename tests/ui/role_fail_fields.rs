@@ -0,0 +1,7 @@
+#[repr(u8)]
+#[derive(Clone, Copy, auth_middleware::Role)]
+enum AppRole {
+    Auditor(u8),
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+#[derive(Clone, Copy, auth_middleware::Role)]
+enum AppRole {
+    Auditor = 10,
+}
+
+fn main() {}
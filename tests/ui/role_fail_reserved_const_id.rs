@@ -0,0 +1,9 @@
+const BASE: u8 = 250;
+
+#[repr(u8)]
+#[derive(Clone, Copy, auth_middleware::Role)]
+enum AppRole {
+    Moderator = BASE + 5,
+}
+
+fn main() {}
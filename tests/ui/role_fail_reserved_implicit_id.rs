@@ -0,0 +1,8 @@
+#[repr(u8)]
+#[derive(Clone, Copy, auth_middleware::Role)]
+enum AppRole {
+    Auditor = 99,
+    Moderator,
+}
+
+fn main() {}
@@ -0,0 +1,23 @@
+use auth_middleware::auth::{AuthRole, Permission, RoleRegistry};
+
+#[repr(u8)]
+#[derive(Clone, Copy, auth_middleware::Role)]
+enum AppRole {
+    ReportViewer = 10,
+    Auditor,
+    HTTPAdmin,
+}
+
+fn main() {
+    assert_eq!(AppRole::ReportViewer.name(), "report_viewer");
+    assert_eq!(u8::from(AppRole::Auditor), 11);
+    assert_eq!(AppRole::HTTPAdmin.name(), "http_admin");
+    assert_eq!(AppRole::VARIANTS.len(), 3);
+    assert_eq!(AppRole::Auditor.permission(), Permission::Role(11));
+
+    let registry = RoleRegistry::default().register::<AppRole>().unwrap();
+    assert_eq!(registry.by_name("auditor"), Some(11));
+    assert_eq!(registry.by_name("admin"), Some(255));
+    assert_eq!(registry.names(&[255, 10]), ["report_viewer", "admin"]);
+    assert!(registry.register::<AppRole>().is_err());
+}
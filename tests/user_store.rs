@@ -0,0 +1,332 @@
+//! The behavior every [`UserStore`] must share, run against each one.
+
+#![cfg(feature = "ssr")]
+
+use auth_middleware::auth::{
+    Error, ExternalIdentity, Group, LoginMethod, Token, TokenKind, User, UserId, UserStore,
+};
+use time::{Duration, OffsetDateTime};
+
+/// Now, in whole seconds as SQLite keeps it.
+fn now() -> OffsetDateTime {
+    OffsetDateTime::from_unix_timestamp(OffsetDateTime::now_utc().unix_timestamp()).unwrap()
+}
+
+fn user(id: &str, username: &str) -> User {
+    User {
+        id: UserId(id.into()),
+        username: username.into(),
+        pw_hash: "$argon2id$hash".into(),
+        roles: vec![100],
+        verified: false,
+        totp_secret: None,
+        identities: Vec::new(),
+    }
+}
+
+async fn users(store: &impl UserStore) {
+    store.insert_user(&user("u1", "alice")).await.unwrap();
+    assert!(matches!(
+        store.insert_user(&user("u2", "alice")).await,
+        Err(Error::UsernameTaken)
+    ));
+
+    let alice = store.get_user_by_username("alice").await.unwrap().unwrap();
+    assert_eq!(alice.id.0, "u1");
+    assert_eq!(alice.roles, vec![100]);
+    assert!(store.get_user("u2").await.unwrap().is_none());
+    assert!(store.get_user_by_username("bob").await.unwrap().is_none());
+
+    store.update_password("u1", "$argon2id$new").await.unwrap();
+    store.set_verified("u1", true).await.unwrap();
+    store.update_roles("u1", &[100, 150]).await.unwrap();
+    let alice = store.get_user("u1").await.unwrap().unwrap();
+    assert_eq!(alice.pw_hash, "$argon2id$new");
+    assert!(alice.verified);
+    let mut roles = alice.roles;
+    roles.sort_unstable();
+    assert_eq!(roles, vec![100, 150]);
+
+    store.delete_user("u1").await.unwrap();
+    assert!(store.get_user("u1").await.unwrap().is_none());
+    store.insert_user(&user("u2", "alice")).await.unwrap();
+}
+
+async fn second_factor(store: &impl UserStore) {
+    store.insert_user(&user("u1", "alice")).await.unwrap();
+
+    store.set_totp_secret("u1", Some("SECRET")).await.unwrap();
+    let alice = store.get_user("u1").await.unwrap().unwrap();
+    assert_eq!(alice.totp_secret.as_deref(), Some("SECRET"));
+
+    assert!(store.use_totp_step("u1", 10).await.unwrap());
+    assert!(!store.use_totp_step("u1", 10).await.unwrap());
+    assert!(!store.use_totp_step("u1", 9).await.unwrap());
+    assert!(store.use_totp_step("u1", 11).await.unwrap());
+    // A new secret starts over
+    store.set_totp_secret("u1", Some("OTHER")).await.unwrap();
+    assert!(store.use_totp_step("u1", 5).await.unwrap());
+
+    assert_eq!(store.add_second_factor_failure("u1").await.unwrap(), 1);
+    assert_eq!(store.add_second_factor_failure("u1").await.unwrap(), 2);
+    assert!(store.get_second_factor_lock("u1").await.unwrap().is_none());
+    let locked_until = now() + Duration::minutes(15);
+    store
+        .reset_second_factor_failures("u1", Some(locked_until))
+        .await
+        .unwrap();
+    assert_eq!(
+        store.get_second_factor_lock("u1").await.unwrap(),
+        Some(locked_until)
+    );
+    assert_eq!(store.add_second_factor_failure("u1").await.unwrap(), 1);
+    store
+        .reset_second_factor_failures("u1", None)
+        .await
+        .unwrap();
+    assert!(store.get_second_factor_lock("u1").await.unwrap().is_none());
+
+    let hashes = vec!["h1".to_string(), "h2".to_string()];
+    store.replace_recovery_codes("u1", &hashes).await.unwrap();
+    let mut stored = store.get_recovery_codes("u1").await.unwrap();
+    stored.sort();
+    assert_eq!(stored, hashes);
+    assert!(store.take_recovery_code("u1", "h1").await.unwrap());
+    assert!(!store.take_recovery_code("u1", "h1").await.unwrap());
+    assert_eq!(store.get_recovery_codes("u1").await.unwrap(), vec!["h2"]);
+    store.replace_recovery_codes("u1", &[]).await.unwrap();
+    assert!(store.get_recovery_codes("u1").await.unwrap().is_empty());
+}
+
+async fn tokens(store: &impl UserStore) {
+    store.insert_user(&user("u1", "alice")).await.unwrap();
+    let token = |hash: &str, kind| Token {
+        hash: hash.into(),
+        kind,
+        user_id: "u1".into(),
+        expires_at: now() + Duration::hours(1),
+    };
+    store
+        .insert_token(&token("t1", TokenKind::PasswordReset))
+        .await
+        .unwrap();
+    store
+        .insert_token(&token("t2", TokenKind::PasswordReset))
+        .await
+        .unwrap();
+    store
+        .insert_token(&token("t3", TokenKind::Refresh))
+        .await
+        .unwrap();
+
+    assert!(store
+        .get_token(TokenKind::Refresh, "t1")
+        .await
+        .unwrap()
+        .is_none());
+    let taken = store
+        .take_token(TokenKind::PasswordReset, "t1")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(taken.user_id, "u1");
+    assert!(store
+        .take_token(TokenKind::PasswordReset, "t1")
+        .await
+        .unwrap()
+        .is_none());
+
+    store
+        .delete_tokens("u1", TokenKind::PasswordReset)
+        .await
+        .unwrap();
+    assert!(store
+        .get_token(TokenKind::PasswordReset, "t2")
+        .await
+        .unwrap()
+        .is_none());
+    assert!(store
+        .get_token(TokenKind::Refresh, "t3")
+        .await
+        .unwrap()
+        .is_some());
+}
+
+async fn login_methods(store: &impl UserStore) {
+    store.insert_user(&user("u1", "alice")).await.unwrap();
+    let identity = ExternalIdentity {
+        provider: "idp".into(),
+        subject: "sub-1".into(),
+        user_id: "u1".into(),
+        created_at: now(),
+    };
+    store.insert_identity(&identity).await.unwrap();
+    store.insert_user(&user("u2", "bob")).await.unwrap();
+    assert!(matches!(
+        store
+            .insert_identity(&ExternalIdentity {
+                user_id: "u2".into(),
+                ..identity.clone()
+            })
+            .await,
+        Err(Error::IdentityInUse)
+    ));
+    let alice = store
+        .get_user_by_identity("idp", "sub-1")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(alice.id.0, "u1");
+
+    let external = LoginMethod::External {
+        provider: "idp".into(),
+        subject: "sub-1".into(),
+    };
+    assert!(store
+        .delete_login_method("u1", &LoginMethod::Password)
+        .await
+        .unwrap());
+    assert!(!store
+        .delete_login_method("u1", &LoginMethod::Password)
+        .await
+        .unwrap());
+    // The identity is all alice has left
+    assert!(matches!(
+        store.delete_login_method("u1", &external).await,
+        Err(Error::LastLoginMethod)
+    ));
+    assert!(store
+        .get_user_by_identity("idp", "sub-1")
+        .await
+        .unwrap()
+        .is_some());
+}
+
+async fn groups(store: &impl UserStore) {
+    store.insert_user(&user("u1", "alice")).await.unwrap();
+    let group = Group {
+        id: "g1".into(),
+        name: "editors".into(),
+        roles: vec![150],
+        permissions: vec!["reports:read".into()],
+        members: Vec::new(),
+    };
+    store.insert_group(&group).await.unwrap();
+    assert!(matches!(
+        store
+            .insert_group(&Group {
+                id: "g2".into(),
+                ..group.clone()
+            })
+            .await,
+        Err(Error::GroupNameTaken)
+    ));
+
+    assert!(store.add_group_member("g1", "u1").await.unwrap());
+    assert!(!store.add_group_member("g1", "u1").await.unwrap());
+    assert!(matches!(
+        store.add_group_member("g2", "u1").await,
+        Err(Error::UnknownGroup)
+    ));
+    let groups = store.get_user_groups("u1").await.unwrap();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].roles, vec![150]);
+    assert_eq!(groups[0].permissions, vec!["reports:read"]);
+
+    store.update_group_roles("g1", &[255]).await.unwrap();
+    let groups = store.get_groups().await.unwrap();
+    assert_eq!(groups[0].roles, vec![255]);
+    assert_eq!(groups[0].members, vec!["u1"]);
+    store
+        .update_group_permissions("g1", &["reports:write".into(), "data:read".into()])
+        .await
+        .unwrap();
+    let groups = store.get_groups().await.unwrap();
+    let mut permissions = groups[0].permissions.clone();
+    permissions.sort();
+    assert_eq!(permissions, vec!["data:read", "reports:write"]);
+
+    assert!(store.remove_group_member("g1", "u1").await.unwrap());
+    assert!(!store.remove_group_member("g1", "u1").await.unwrap());
+    assert!(store.delete_group("g1").await.unwrap());
+    assert!(!store.delete_group("g1").await.unwrap());
+    assert!(store.get_groups().await.unwrap().is_empty());
+}
+
+/// Runs each check on a fresh store from `new_store`.
+macro_rules! conformance {
+    ($name:ident, $new_store:expr) => {
+        mod $name {
+            #[tokio::test]
+            async fn users() {
+                super::users(&$new_store).await;
+            }
+
+            #[tokio::test]
+            async fn second_factor() {
+                super::second_factor(&$new_store).await;
+            }
+
+            #[tokio::test]
+            async fn tokens() {
+                super::tokens(&$new_store).await;
+            }
+
+            #[tokio::test]
+            async fn login_methods() {
+                super::login_methods(&$new_store).await;
+            }
+
+            #[tokio::test]
+            async fn groups() {
+                super::groups(&$new_store).await;
+            }
+        }
+    };
+}
+
+conformance!(
+    sqlite,
+    auth_middleware::auth::SqliteStore::connect("sqlite::memory:")
+        .await
+        .unwrap()
+);
+#[cfg(feature = "memory-store")]
+conformance!(memory, auth_middleware::auth::MemoryStore::default());
+
+#[repr(u8)]
+#[derive(Clone, Copy, auth_middleware::Role)]
+enum AppRole {
+    Auditor = 10,
+}
+
+#[tokio::test]
+async fn sqlite_assigns_registered_roles() {
+    use auth_middleware::auth::{RoleRegistry, SqliteStore};
+
+    let store = SqliteStore::connect("sqlite::memory:").await.unwrap();
+    let registry = RoleRegistry::default().register::<AppRole>().unwrap();
+    store.register_roles(&registry).await.unwrap();
+
+    let mut auditor = user("u1", "alice");
+    auditor.roles = vec![AppRole::Auditor.into()];
+    store.insert_user(&auditor).await.unwrap();
+    let stored = store.get_user("u1").await.unwrap().unwrap();
+    assert_eq!(stored.roles, [10]);
+
+    store
+        .insert_group(&Group {
+            id: "g1".into(),
+            name: "auditors".into(),
+            roles: vec![AppRole::Auditor.into()],
+            permissions: Vec::new(),
+            members: Vec::new(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(store.get_groups().await.unwrap()[0].roles, [10]);
+
+    // Registering again at the next start keeps the roles
+    store.register_roles(&registry).await.unwrap();
+    assert_eq!(store.get_user("u1").await.unwrap().unwrap().roles, [10]);
+}